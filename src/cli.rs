@@ -0,0 +1,97 @@
+use clap::Parser;
+
+use crate::log::LogFormat;
+
+/// The command line flags azel understands.
+///
+/// Bots with flags of their own flatten this into their own parser and implement [`CliArguments`]:
+///
+/// ```ignore
+/// #[derive(clap::Parser)]
+/// struct Cli {
+///     #[command(flatten)]
+///     azel: azel::Arguments,
+///     #[arg(long)]
+///     skip_migrations: bool,
+/// }
+///
+/// impl azel::CliArguments for Cli {
+///     fn azel(&self) -> &azel::Arguments {
+///         &self.azel
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, clap::Args)]
+pub struct Arguments {
+    /// Path to the configuration file.
+    #[arg(short, long = "config", value_name = "PATH", required_unless_present = "positional_cfg_path")]
+    cfg_path: Option<String>,
+    /// Path to the configuration file, accepted positionally for older deployments.
+    #[arg(value_name = "CONFIG", conflicts_with = "cfg_path", hide = true)]
+    positional_cfg_path: Option<String>,
+    /// Log filter in `RUST_LOG` syntax, e.g. `info,azel=debug`. Replaces `logging.level` and `logging.modules`.
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+    /// Format of log output. Replaces `logging.format`.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Register commands with Discord and exit without connecting to the gateway.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub register_only: bool,
+    /// Load configuration and build commands, then exit without contacting Discord.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Write the global and per-guild command payloads as JSON to this path, then exit without contacting Discord.
+    #[arg(long, value_name = "PATH", conflicts_with = "register_only")]
+    pub export_commands: Option<String>,
+}
+
+impl Arguments {
+    pub fn cfg_path(&self) -> &str {
+        self.cfg_path.as_deref()
+            .or(self.positional_cfg_path.as_deref())
+            .expect("clap requires one of --config or CONFIG")
+    }
+}
+
+/// Implemented by a bot's own parser so azel can find its flags inside it.
+pub trait CliArguments: Parser {
+    fn azel(&self) -> &Arguments;
+}
+
+#[derive(Debug, Clone, Parser)]
+#[command(version, about)]
+pub struct DefaultCli {
+    #[command(flatten)]
+    pub azel: Arguments,
+}
+
+impl CliArguments for DefaultCli {
+    fn azel(&self) -> &Arguments {
+        &self.azel
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::DefaultCli;
+
+    #[test]
+    fn config_flag_or_positional() {
+        let cli = DefaultCli::try_parse_from(["bot", "--config", "bot.toml", "--dry-run"]).unwrap();
+        assert_eq!(cli.azel.cfg_path(), "bot.toml");
+        assert!(cli.azel.dry_run);
+
+        let cli = DefaultCli::try_parse_from(["bot", "bot.toml"]).unwrap();
+        assert_eq!(cli.azel.cfg_path(), "bot.toml");
+    }
+
+    #[test]
+    fn bad_invocations_are_rejected() {
+        assert!(DefaultCli::try_parse_from(["bot"]).is_err());
+        assert!(DefaultCli::try_parse_from(["bot", "-c", "a.toml", "b.toml"]).is_err());
+        assert!(DefaultCli::try_parse_from(["bot", "-c", "a.toml", "--dry-run", "--register-only"]).is_err());
+    }
+}
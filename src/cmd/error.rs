@@ -0,0 +1,207 @@
+use std::{borrow::Cow, error::Error, fmt, hash::{BuildHasher, RandomState}, sync::atomic::{AtomicU64, Ordering}, time::SystemTime};
+
+use serenity::async_trait;
+use tracing as trc;
+
+use crate::{discord::ExecutionContext, ops::OpsReport, panics::Panic};
+
+pub type ErrorSource = Box<dyn Error + Send + Sync + 'static>;
+
+/// What went wrong, and optionally the error that caused it.
+#[derive(Debug)]
+pub struct ErrorDetail {
+    /// Shown to the invoker, except for internal errors where it is only logged.
+    pub reason: Cow<'static, str>,
+    pub source: Option<ErrorSource>,
+}
+
+impl ErrorDetail {
+    pub fn new(reason: impl Into<Cow<'static, str>>) -> Self {
+        Self { reason: reason.into(), source: None }
+    }
+
+    pub fn with_source(mut self, source: impl Into<ErrorSource>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// The source error and everything it was caused by, outermost first, joined by `: `.
+    pub fn causes(&self) -> String {
+        let mut causes = String::new();
+        let mut next = self.source.as_deref().map(|e| e as &(dyn Error + 'static));
+        while let Some(cause) = next {
+            if !causes.is_empty() {
+                causes.push_str(": ");
+            }
+            causes.push_str(&cause.to_string());
+            next = cause.source();
+        }
+        causes
+    }
+}
+
+impl From<&'static str> for ErrorDetail {
+    fn from(reason: &'static str) -> Self {
+        Self::new(reason)
+    }
+}
+
+impl From<String> for ErrorDetail {
+    fn from(reason: String) -> Self {
+        Self::new(reason)
+    }
+}
+
+impl From<Cow<'static, str>> for ErrorDetail {
+    fn from(reason: Cow<'static, str>) -> Self {
+        Self::new(reason)
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    /// The invoker made a mistake the reason explains.
+    User(ErrorDetail),
+    /// Something failed on the bot's side. The invoker only sees a correlation id.
+    Internal(ErrorDetail),
+    /// The invoker, or the bot, lacks a permission the command needs.
+    PermissionDenied(ErrorDetail),
+    /// Something the command refers to does not exist.
+    NotFound(ErrorDetail),
+    /// The invoker, or the bot against Discord, is going too fast.
+    RateLimited(ErrorDetail),
+    /// An option was given a value the command cannot use.
+    InvalidArgument(ErrorDetail),
+}
+
+impl RequestError {
+    /// Short, stable name of the variant, for structured logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::User(_) => "user",
+            Self::Internal(_) => "internal",
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::NotFound(_) => "not_found",
+            Self::RateLimited(_) => "rate_limited",
+            Self::InvalidArgument(_) => "invalid_argument",
+        }
+    }
+
+    pub fn detail(&self) -> &ErrorDetail {
+        match self {
+            Self::User(detail)
+            | Self::Internal(detail)
+            | Self::PermissionDenied(detail)
+            | Self::NotFound(detail)
+            | Self::RateLimited(detail)
+            | Self::InvalidArgument(detail) => detail,
+        }
+    }
+
+    /// Logs the error and hands it to the context's [`ErrorReporter`] to tell the invoker.
+    ///
+    /// Internal errors are logged with a fresh correlation id, which the reporter can show the
+    /// invoker so mods can find the log line, and are posted to the ops channel if there is one and
+    /// the reporter's [`ErrorReporter::forward_to_ops`] allows it.
+    pub async fn report(self, ctx: &ExecutionContext<'_>) -> Result<(), RequestError> {
+        let detail = self.detail();
+        let correlation_id = match self {
+            Self::Internal(_) => {
+                let correlation_id = correlation_id();
+                trc::error!(correlation_id, reason = %detail.reason, causes = detail.causes(), "REQ-ERR-INTERNAL");
+                Some(correlation_id)
+            },
+            _ => {
+                trc::warn!(kind = self.kind(), reason = %detail.reason, causes = detail.causes(), "REQ-ERR-USER");
+                None
+            },
+        };
+        let reported = ctx.error_reporter.report(ctx, &self, correlation_id.as_deref()).await;
+        if let (Some(ops), Some(correlation_id)) = (ctx.ops_channel, correlation_id.as_deref())
+            && ctx.error_reporter.forward_to_ops(&self)
+        {
+            let panicked = detail.source.as_ref().is_some_and(|source| source.is::<Panic>());
+            ops.forward(&ctx.ctx.http, OpsReport {
+                kind: if panicked { "panic" } else { "internal" },
+                command: &super::command_path(ctx.cmd),
+                user_id: ctx.cmd.user.id,
+                guild_id: ctx.cmd.guild_id,
+                reason: &detail.reason,
+                causes: &detail.causes(),
+                correlation_id,
+            }).await;
+        }
+        reported
+    }
+}
+
+/// Decides how a failed command is presented to the invoker: wording, visibility, formatting, and
+/// anywhere else it should go. Errors are already logged when a reporter sees them.
+#[async_trait]
+pub trait ErrorReporter: Send + Sync {
+    /// `correlation_id` is set for internal errors, and matches the logged one.
+    async fn report(&self, ctx: &ExecutionContext<'_>, err: &RequestError, correlation_id: Option<&str>) -> Result<(), RequestError>;
+
+    /// Whether an internal error is also posted to the ops channel, if one is configured.
+    ///
+    /// Reporters that post elsewhere themselves, or want to keep some errors out of the channel,
+    /// return `false` for them.
+    fn forward_to_ops(&self, _err: &RequestError) -> bool {
+        true
+    }
+}
+
+/// Replies ephemerally with the reason, or for internal errors a generic message with the correlation id.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultErrorReporter;
+
+#[async_trait]
+impl ErrorReporter for DefaultErrorReporter {
+    async fn report(&self, ctx: &ExecutionContext<'_>, err: &RequestError, correlation_id: Option<&str>) -> Result<(), RequestError> {
+        match (err, correlation_id) {
+            (RequestError::Internal(_), Some(correlation_id)) => {
+                ctx.reply_ephemeral(format!("Something broke! Please contact a mod for help and mention error `{correlation_id}`.")).await
+            },
+            (RequestError::Internal(_), None) => ctx.reply_ephemeral("Something broke! Please contact a mod for help.".to_owned()).await,
+            _ => ctx.reply_ephemeral(err.detail().reason.to_string()).await,
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind(), self.detail().reason)
+    }
+}
+
+impl Error for RequestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.detail().source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+/// A short id tying an error shown to a user to its log line. Unique enough to search logs by.
+pub fn correlation_id() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let hash = RandomState::new().hash_one((SystemTime::now(), SEQUENCE.fetch_add(1, Ordering::Relaxed)));
+    format!("{:08x}", hash as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{correlation_id, ErrorDetail, RequestError};
+
+    #[test]
+    fn detail_lists_every_cause() {
+        let io = std::io::Error::other("disk full");
+        let err = RequestError::Internal(ErrorDetail::new("export failed").with_source(io));
+        assert_eq!(err.kind(), "internal");
+        assert_eq!(err.to_string(), "internal: export failed");
+        assert_eq!(err.detail().causes(), "disk full");
+        assert!(std::error::Error::source(&err).is_some());
+
+        let id = correlation_id();
+        assert_eq!(id.len(), 8);
+        assert_ne!(id, correlation_id());
+    }
+}
@@ -1,10 +1,16 @@
-use std::{borrow::Cow, fmt::Debug, hash::Hash};
+use std::{fmt::Debug, hash::Hash, path::Path};
 use tracing as trc;
 
-use serenity::{all::{CommandInteraction, CommandOptionType, CommandType}, builder::{CreateCommand, CreateCommandOption}, model::Permissions};
+use serenity::{all::{CommandDataOptionValue, CommandInteraction, CommandOptionType, CommandType, GuildId}, builder::{CreateCommand, CreateCommandOption}, http::Http, model::Permissions};
 use strum::{EnumCount, IntoEnumIterator};
 
-use crate::discord::ExecutionContext;
+use crate::discord::{AutoDefer, ExecutionContext};
+
+mod error;
+#[cfg(feature = "database")]
+pub mod settings;
+
+pub use error::{DefaultErrorReporter, ErrorDetail, ErrorReporter, ErrorSource, RequestError};
 
 pub trait DiscordCommandDescriptor: Debug + Clone + Copy + PartialEq + Eq + Hash + EnumCount + IntoEnumIterator + Send + Sync + 'static {
     type Args<'a>: DiscordCommandArgs + 'a;
@@ -278,36 +284,104 @@ impl <R: DiscordCommandDescriptor> CommandTreeTop<R> {
     }
 }
 
-pub trait DiscordCommandArgs: Debug + Sized + Send {
-    fn execute(self, ctx: &ExecutionContext<'_>) -> impl std::future::Future<Output = Result<(), RequestError>> + Send;
+/// The invoked command with its subcommand group and subcommand, e.g. `settings set`.
+pub fn command_path(cmd: &CommandInteraction) -> String {
+    let mut path = vec![cmd.data.name.as_str()];
+    let mut options = cmd.data.options.as_slice();
+    while let Some(option) = options.first() {
+        match &option.value {
+            CommandDataOptionValue::SubCommandGroup(children) | CommandDataOptionValue::SubCommand(children) => {
+                path.push(option.name.as_str());
+                options = children.as_slice();
+            },
+            _ => break,
+        }
+    }
+    path.join(" ")
 }
 
-#[derive(Debug)]
-pub struct Request<'a, RequestKind: DiscordCommandDescriptor> {
-    pub args: RequestKind::Args<'a>,
+/// Splits the command trees into `(global, per_guild)` Discord commands.
+pub fn partition_commands<R: DiscordCommandDescriptor>(command_descriptions: &[CommandTreeTop<R>]) -> (Vec<CreateCommand>, Vec<CreateCommand>) {
+    let (global, local): (Vec<_>, Vec<_>) = command_descriptions.iter().cloned().partition(|cmd| cmd.is_global());
+    (
+        global.into_iter().map(|ctt| ctt.into_discord_command()).collect(),
+        local.into_iter().map(|ctt| ctt.into_discord_command()).collect(),
+    )
 }
 
-#[derive(Debug)]
-pub enum RequestError {
-    User(Cow<'static, str>),
-    Internal(Cow<'static, str>),
+/// The full command set as it would be sent to Discord, for inspecting or diffing without connecting.
+#[derive(Debug, serde::Serialize)]
+pub struct CommandExport {
+    pub global: Vec<CreateCommand>,
+    pub guild: Vec<CreateCommand>,
 }
 
-impl RequestError {
-    pub async fn report(self, ctx: &ExecutionContext<'_>) -> Result<(), RequestError> {
-        match self {
-            Self::User(reason) => {
-                trc::warn!("REQ-ERR-USER reason={}", reason);
-                ctx.reply_restricted(reason.to_string()).await
-            },
-            Self::Internal(reason) => {
-                trc::error!("REQ-ERR-INTERNAL reason={}", reason);
-                ctx.reply_restricted("Something broke! Please contact a mod for help.".to_owned()).await
-            },
-        }
+impl CommandExport {
+    pub fn new<R: DiscordCommandDescriptor>(command_descriptions: &[CommandTreeTop<R>]) -> Self {
+        let (global, guild) = partition_commands(command_descriptions);
+        Self { global, guild }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut json = self.to_json()?;
+        json.push('\n');
+        std::fs::write(path, json)
+    }
+}
+
+/// Overwrites the commands of every listed guild with the per-guild set, then the global set.
+pub async fn register_commands<R: DiscordCommandDescriptor>(
+    http: &Http,
+    guilds: impl IntoIterator<Item = GuildId>,
+    command_descriptions: &[CommandTreeTop<R>],
+) -> serenity::Result<()> {
+    register_guild_commands(http, guilds, command_descriptions).await?;
+    register_global_commands(http, command_descriptions).await
+}
+
+/// Overwrites the commands of every listed guild with the per-guild set.
+pub async fn register_guild_commands<R: DiscordCommandDescriptor>(
+    http: &Http,
+    guilds: impl IntoIterator<Item = GuildId>,
+    command_descriptions: &[CommandTreeTop<R>],
+) -> serenity::Result<()> {
+    let (_, local_commands) = partition_commands(command_descriptions);
+    for guild in guilds {
+        trc::info!(guild_id = guild.get(), "CMD-SETUP-GUILD");
+        guild.set_commands(http, local_commands.clone()).await?;
+    }
+    Ok(())
+}
+
+/// Overwrites the global command set.
+pub async fn register_global_commands<R: DiscordCommandDescriptor>(http: &Http, command_descriptions: &[CommandTreeTop<R>]) -> serenity::Result<()> {
+    let (global_commands, _) = partition_commands(command_descriptions);
+    http.create_global_commands(&global_commands).await?;
+    trc::info!("CMD-SETUP-GLOBAL");
+    Ok(())
+}
+
+pub trait DiscordCommandArgs: Debug + Sized + Send {
+    fn execute(self, ctx: &ExecutionContext<'_>) -> impl std::future::Future<Output = Result<(), RequestError>> + Send;
+
+    /// How the command is deferred if it has not replied within [`crate::discord::AUTO_DEFER_AFTER`].
+    ///
+    /// Commands whose replies are ephemeral should return [`AutoDefer::Ephemeral`], since the first
+    /// followup after a public defer is shown to everyone.
+    fn auto_defer(&self) -> AutoDefer {
+        AutoDefer::Public
     }
 }
 
+#[derive(Debug)]
+pub struct Request<'a, RequestKind: DiscordCommandDescriptor> {
+    pub args: RequestKind::Args<'a>,
+}
+
 impl <'a, RequestKind: DiscordCommandDescriptor> Request<'a, RequestKind> {
     pub fn parse(cmd: &'a CommandInteraction) -> Result<Self, RequestError> {
         Ok(Request {
@@ -315,6 +389,10 @@ impl <'a, RequestKind: DiscordCommandDescriptor> Request<'a, RequestKind> {
         })
     }
 
+    pub fn auto_defer(&self) -> AutoDefer {
+        self.args.auto_defer()
+    }
+
     pub async fn execute(self, ctx: &ExecutionContext<'_>) -> Result<(), RequestError> {
         self.args.execute(ctx).await
     }
@@ -322,9 +400,36 @@ impl <'a, RequestKind: DiscordCommandDescriptor> Request<'a, RequestKind> {
 
 #[cfg(any(feature = "test-utils", test))]
 pub mod test_utils {
+    use std::path::Path;
+
     use strum::IntoEnumIterator;
 
-    use crate::cmd::DiscordCommandDescriptor;
+    use crate::cmd::{CommandExport, CommandTreeTop, DiscordCommandDescriptor};
+
+    /// Set to rewrite snapshots instead of comparing against them.
+    pub const UPDATE_SNAPSHOTS_VAR: &str = "AZEL_UPDATE_SNAPSHOTS";
+
+    /// Compares the exported command set against the JSON snapshot at `path`.
+    ///
+    /// The snapshot is written instead when `AZEL_UPDATE_SNAPSHOTS` is set. A missing snapshot fails,
+    /// so a deleted or renamed one is caught rather than silently recreated.
+    pub fn assert_command_snapshot<R: DiscordCommandDescriptor>(command_descriptions: &[CommandTreeTop<R>], path: impl AsRef<Path>) {
+        check_command_snapshot(command_descriptions, path.as_ref(), std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some());
+    }
+
+    pub(super) fn check_command_snapshot<R: DiscordCommandDescriptor>(command_descriptions: &[CommandTreeTop<R>], path: &Path, update: bool) {
+        let export = CommandExport::new(command_descriptions);
+        if update {
+            export.write(path).expect("snapshot to be written");
+            return;
+        }
+        let expected = match std::fs::read_to_string(path) {
+            Ok(expected) => expected,
+            Err(e) => panic!("command snapshot {path:?} could not be read ({e}), rerun with {UPDATE_SNAPSHOTS_VAR}=1 to create it"),
+        };
+        let actual = export.to_json().expect("commands to serialize");
+        assert_eq!(expected.trim_end(), actual, "command snapshot {path:?} is out of date, rerun with {UPDATE_SNAPSHOTS_VAR}=1 to update it");
+    }
 
     pub fn test_command_description_lengths<RK: IntoEnumIterator + DiscordCommandDescriptor>() {
         for c in RK::iter() {
@@ -347,7 +452,7 @@ pub mod test {
 
     use crate::cmd::test_utils::test_command_description_lengths;
 
-    use super::{CommandTreeTop, RawCommandOptionEntry};
+    use super::{CommandExport, CommandTreeTop, RawCommandOptionEntry};
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumCount, EnumIter)]
     pub enum TestRequestKind {
@@ -434,8 +539,56 @@ pub mod test {
         assert_eq!(found_commands.len(), TestRequestKind::COUNT);
     }
 
+    #[test]
+    fn export_matches_snapshot() {
+        let export = CommandExport::new(&generate_command_descriptions());
+        let expected = serde_json::json!({
+            "global": [],
+            "guild": [
+                {
+                    "name": "ping",
+                    "name_localizations": {},
+                    "description": "Ping!",
+                    "description_localizations": {},
+                    "type": 1,
+                    "options": [],
+                    "nsfw": false,
+                },
+            ],
+        });
+        let actual: serde_json::Value = serde_json::from_str(&export.to_json().unwrap()).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn snapshot_helper_fails_on_missing_or_stale_snapshot() {
+        use super::test_utils::check_command_snapshot;
+
+        let path = std::env::temp_dir().join(format!("azel-snapshot-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let commands = generate_command_descriptions();
+
+        let missing = std::panic::catch_unwind(|| check_command_snapshot(&commands, &path, false));
+        assert!(missing.is_err());
+        assert!(!path.exists());
+
+        check_command_snapshot(&commands, &path, true);
+        check_command_snapshot(&commands, &path, false);
+
+        std::fs::write(&path, "{}").unwrap();
+        let stale = std::panic::catch_unwind(|| check_command_snapshot(&commands, &path, false));
+        std::fs::remove_file(&path).unwrap();
+        assert!(stale.is_err());
+    }
+
     #[test]
     fn description_not_too_long() {
         test_command_description_lengths::<TestRequestKind>();
     }
+
+    #[cfg(feature = "database")]
+    #[test]
+    fn settings_description_not_too_long() {
+        test_command_description_lengths::<super::settings::SettingsCommand>();
+    }
 }
@@ -0,0 +1,165 @@
+use serenity::{all::{CommandInteraction, CommandType, ResolvedOption, ResolvedValue}, model::Permissions};
+use strum::{EnumCount, EnumIter, IntoEnumIterator};
+use tracing as trc;
+
+use crate::discord::ExecutionContext;
+
+use super::{CommandTreeTop, DiscordCommandArgs, DiscordCommandDescriptor, RawCommandOptionEntry, RequestError};
+
+pub const GROUP_NAME: &str = "settings";
+const MAX_KEY_LEN: usize = 64;
+const MAX_VALUE_LEN: usize = 1000;
+
+/// The subcommands of the generic `/settings` group.
+///
+/// Bots either use this directly as their descriptor or wrap it in one of their own variants,
+/// delegating `name`, `description`, `options` and `parse` back here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumCount, EnumIter)]
+pub enum SettingsCommand {
+    Show,
+    Set,
+    Reset,
+}
+
+impl SettingsCommand {
+    /// Builds the `/settings` group, mapping each subcommand into the bot's own descriptor.
+    pub fn command_tree<R>(wrap: impl Fn(Self) -> R) -> CommandTreeTop<R> {
+        CommandTreeTop::Complex {
+            name: GROUP_NAME,
+            description: "View or change this server's bot settings.",
+            kind: CommandType::ChatInput,
+            subcommand_groups: vec![],
+            subcommands: Self::iter().map(wrap).collect(),
+            opt_default_perm: Some(Permissions::MANAGE_GUILD),
+        }
+    }
+}
+
+impl DiscordCommandDescriptor for SettingsCommand {
+    type Args<'a> = SettingsArgs;
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Show => "show",
+            Self::Set => "set",
+            Self::Reset => "reset",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Show => "Show all settings for this server.",
+            Self::Set => "Change a setting for this server.",
+            Self::Reset => "Reset a setting for this server to its default.",
+        }
+    }
+
+    fn options(&self) -> Vec<RawCommandOptionEntry> {
+        match self {
+            Self::Show => vec![],
+            Self::Set => vec![
+                RawCommandOptionEntry::String {
+                    name: "key",
+                    description: "Name of the setting.",
+                    required: true,
+                },
+                RawCommandOptionEntry::String {
+                    name: "value",
+                    description: "New value for the setting.",
+                    required: true,
+                },
+            ],
+            Self::Reset => vec![
+                RawCommandOptionEntry::String {
+                    name: "key",
+                    description: "Name of the setting.",
+                    required: true,
+                },
+            ],
+        }
+    }
+
+    fn parse<'a>(cmd: &'a CommandInteraction) -> Result<Self::Args<'a>, RequestError> {
+        if cmd.data.name != GROUP_NAME {
+            trc::error!("Unknown command {:?} received", cmd);
+            return Err(RequestError::Internal("Unknown command.".into()));
+        }
+
+        let options = cmd.data.options();
+        let Some(ResolvedOption { name, value: ResolvedValue::SubCommand(sub_options), .. }) = options.into_iter().next() else {
+            return Err(RequestError::Internal("Settings subcommand missing.".into()));
+        };
+
+        let string_option = |wanted: &str| {
+            sub_options.iter().find_map(|o| match o {
+                ResolvedOption { name, value: ResolvedValue::String(s), .. } if *name == wanted => Some(s.to_string()),
+                _ => None,
+            }).ok_or_else(|| RequestError::Internal(format!("Settings option {wanted} missing.").into()))
+        };
+
+        match name {
+            "show" => Ok(SettingsArgs::Show),
+            "set" => {
+                let key = validate_key(string_option("key")?)?;
+                let value = string_option("value")?;
+                if value.chars().count() > MAX_VALUE_LEN {
+                    return Err(RequestError::InvalidArgument(format!("Setting values can be at most {MAX_VALUE_LEN} characters.").into()));
+                }
+                Ok(SettingsArgs::Set { key, value })
+            },
+            "reset" => Ok(SettingsArgs::Reset { key: validate_key(string_option("key")?)? }),
+            _ => {
+                trc::error!("Unknown settings subcommand {:?} received", name);
+                Err(RequestError::Internal("Unknown command.".into()))
+            },
+        }
+    }
+}
+
+fn validate_key(key: String) -> Result<String, RequestError> {
+    let key = key.trim().to_lowercase();
+    if key.is_empty() || key.len() > MAX_KEY_LEN || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(RequestError::InvalidArgument(format!("Setting names must be 1 to {MAX_KEY_LEN} letters, digits, `_` or `-`.").into()));
+    }
+    Ok(key)
+}
+
+#[derive(Debug)]
+pub enum SettingsArgs {
+    Show,
+    Set {
+        key: String,
+        value: String,
+    },
+    Reset {
+        key: String,
+    },
+}
+
+impl DiscordCommandArgs for SettingsArgs {
+    async fn execute(self, ctx: &ExecutionContext<'_>) -> Result<(), RequestError> {
+        match self {
+            Self::Show => {
+                let settings = ctx.guild_settings().await?;
+                if settings.is_empty() {
+                    return ctx.reply_restricted("No settings have been configured for this server.".to_owned()).await;
+                }
+                let mut entries: Vec<_> = settings.iter().collect();
+                entries.sort();
+                let listing: Vec<_> = entries.into_iter().map(|(k, v)| format!("`{k}` = `{v}`")).collect();
+                ctx.reply_restricted(listing.join("\n")).await
+            },
+            Self::Set { key, value } => {
+                ctx.set_guild_setting(key.as_str(), value.as_str()).await?;
+                ctx.reply_restricted(format!("Set `{key}` to `{value}`.")).await
+            },
+            Self::Reset { key } => {
+                if ctx.reset_guild_setting(key.as_str()).await? {
+                    ctx.reply_restricted(format!("Reset `{key}`.")).await
+                } else {
+                    ctx.reply_restricted(format!("`{key}` was not set.")).await
+                }
+            },
+        }
+    }
+}
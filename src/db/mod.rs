@@ -1,5 +1,24 @@
-use diesel::{Connection, ConnectionError, PgConnection};
-use diesel_async::{AsyncConnection, AsyncPgConnection};
+use diesel::ConnectionError;
+use diesel::Connection;
+use diesel_async::AsyncConnection;
+
+#[cfg(feature = "postgres")]
+use diesel::PgConnection;
+#[cfg(feature = "postgres")]
+use diesel_async::AsyncPgConnection;
+#[cfg(feature = "sqlite")]
+use diesel::SqliteConnection;
+#[cfg(feature = "sqlite")]
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+#[cfg(feature = "mysql")]
+use diesel::MysqlConnection;
+#[cfg(feature = "mysql")]
+use diesel_async::AsyncMysqlConnection;
+
+pub mod settings;
+
+#[cfg(not(any(feature = "postgres", feature = "sqlite", feature = "mysql")))]
+compile_error!("the `database` feature needs at least one of the `postgres`, `sqlite` or `mysql` features enabled");
 
 treeerror::treeerror! {
     #[derive(Debug)]
@@ -9,19 +28,239 @@ treeerror::treeerror! {
     },
 }
 
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connection(_) => write!(f, "database connection failed"),
+            Self::Query(_) => write!(f, "database query failed"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connection(e) => Some(e),
+            Self::Query(e) => Some(e),
+        }
+    }
+}
+
 pub type DbResult<T> = Result<T, DbError>;
 
+/// Runs `$body` with `$c` bound to the concrete connection inside an [`AnyConnection`] or
+/// [`AnyAsyncConnection`], so a single diesel query can be written once for every enabled backend.
+macro_rules! with_connection {
+    ($any:ident, $conn:expr, $c:ident => $body:expr) => {
+        match $conn {
+            #[cfg(feature = "postgres")]
+            $crate::db::$any::Postgres($c) => $body,
+            #[cfg(feature = "sqlite")]
+            $crate::db::$any::Sqlite($c) => $body,
+            #[cfg(feature = "mysql")]
+            $crate::db::$any::Mysql($c) => $body,
+        }
+    };
+}
+pub(crate) use with_connection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Postgres,
+    Sqlite,
+    Mysql,
+}
+
+impl Backend {
+    /// Picks the backend from the scheme of a database URL, or `None` for a scheme no backend handles.
+    ///
+    /// Postgres accepts `postgres://` and `postgresql://`, MySQL accepts `mysql://`, and SQLite
+    /// accepts `sqlite://`, `sqlite:` and `file:` as well as bare paths and `:memory:`.
+    pub fn from_url(url: &str) -> Option<Self> {
+        match url_scheme(url) {
+            Some("postgres" | "postgresql") => Some(Self::Postgres),
+            Some("mysql") => Some(Self::Mysql),
+            None | Some("sqlite" | "file") => Some(Self::Sqlite),
+            Some(_) => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::Sqlite => "sqlite",
+            Self::Mysql => "mysql",
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Self::Postgres => cfg!(feature = "postgres"),
+            Self::Sqlite => cfg!(feature = "sqlite"),
+            Self::Mysql => cfg!(feature = "mysql"),
+        }
+    }
+
+    fn disabled_error(self) -> ConnectionError {
+        ConnectionError::InvalidConnectionUrl(format!("azel was built without the {self:?} backend enabled"))
+    }
+}
+
+/// The scheme of `url`, or `None` for a bare path or `:memory:`.
+pub fn url_scheme(url: &str) -> Option<&str> {
+    url.split_once(':').map(|(scheme, _)| scheme).filter(|scheme| !scheme.is_empty())
+}
+
+fn unknown_scheme_error(url: &str) -> ConnectionError {
+    ConnectionError::InvalidConnectionUrl(format!("unsupported database URL scheme `{}`", url_scheme(url).unwrap_or_default()))
+}
+
+/// Strips the `sqlite://` or `sqlite:` prefix, since diesel expects a path or a `file:` URI.
+#[cfg(feature = "sqlite")]
+fn sqlite_path(url: &str) -> &str {
+    url.strip_prefix("sqlite://").or_else(|| url.strip_prefix("sqlite:")).unwrap_or(url)
+}
+
+/// A blocking connection to whichever backend the URL selected.
+///
+/// The variants depend on the enabled backend features, so code outside this crate should use the
+/// `as_*_mut`/`into_*` accessors rather than matching on them.
+#[non_exhaustive]
+pub enum AnyConnection {
+    #[cfg(feature = "postgres")]
+    Postgres(PgConnection),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteConnection),
+    #[cfg(feature = "mysql")]
+    Mysql(MysqlConnection),
+}
+
+/// An async connection to whichever backend the URL selected.
+///
+/// The variants depend on the enabled backend features, so code outside this crate should use the
+/// `as_*_mut`/`into_*` accessors rather than matching on them.
+#[non_exhaustive]
+pub enum AnyAsyncConnection {
+    #[cfg(feature = "postgres")]
+    Postgres(AsyncPgConnection),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SyncConnectionWrapper<SqliteConnection>),
+    #[cfg(feature = "mysql")]
+    Mysql(AsyncMysqlConnection),
+}
+
+/// Defines `as_<name>_mut` and `into_<name>` for one backend's variant, returning `None` for the others.
+macro_rules! connection_accessors {
+    ($any:ident, $feature:literal, $variant:ident, $conn:ty, $as_mut:ident, $into:ident) => {
+        #[cfg(feature = $feature)]
+        impl $any {
+            pub fn $as_mut(&mut self) -> Option<&mut $conn> {
+                #[allow(unreachable_patterns)]
+                match self {
+                    Self::$variant(c) => Some(c),
+                    _ => None,
+                }
+            }
+
+            pub fn $into(self) -> Option<$conn> {
+                #[allow(unreachable_patterns)]
+                match self {
+                    Self::$variant(c) => Some(c),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+connection_accessors!(AnyConnection, "postgres", Postgres, PgConnection, as_pg_mut, into_pg);
+connection_accessors!(AnyConnection, "sqlite", Sqlite, SqliteConnection, as_sqlite_mut, into_sqlite);
+connection_accessors!(AnyConnection, "mysql", Mysql, MysqlConnection, as_mysql_mut, into_mysql);
+connection_accessors!(AnyAsyncConnection, "postgres", Postgres, AsyncPgConnection, as_pg_mut, into_pg);
+connection_accessors!(AnyAsyncConnection, "sqlite", Sqlite, SyncConnectionWrapper<SqliteConnection>, as_sqlite_mut, into_sqlite);
+connection_accessors!(AnyAsyncConnection, "mysql", Mysql, AsyncMysqlConnection, as_mysql_mut, into_mysql);
+
 pub trait Connector {
-    fn connect(&self) -> Result<PgConnection, ConnectionError>;
-    fn async_connect(&self) -> impl Future<Output = Result<AsyncPgConnection, ConnectionError>> + Send;
+    /// The backend the connector's URL selects, or `None` if its scheme is not supported.
+    fn backend(&self) -> Option<Backend>;
+    fn connect(&self) -> Result<AnyConnection, ConnectionError>;
+    fn async_connect(&self) -> impl Future<Output = Result<AnyAsyncConnection, ConnectionError>> + Send;
 }
 
 impl Connector for &super::DatabaseConfiguration {
-    fn connect(&self) -> Result<PgConnection, ConnectionError> {
-        PgConnection::establish(self.url.as_str())
+    fn backend(&self) -> Option<Backend> {
+        Backend::from_url(self.url.as_str())
+    }
+
+    fn connect(&self) -> Result<AnyConnection, ConnectionError> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let Some(backend) = self.backend() else {
+            return Err(unknown_scheme_error(self.url.as_str()));
+        };
+        let conn = match backend {
+            #[cfg(feature = "postgres")]
+            Backend::Postgres => PgConnection::establish(self.url.as_str()).map(AnyConnection::Postgres),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => SqliteConnection::establish(sqlite_path(self.url.as_str())).map(AnyConnection::Sqlite),
+            #[cfg(feature = "mysql")]
+            Backend::Mysql => MysqlConnection::establish(self.url.as_str()).map(AnyConnection::Mysql),
+            #[allow(unreachable_patterns)]
+            backend => Err(backend.disabled_error()),
+        };
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().record_db_connect(backend.name(), conn.is_ok(), start.elapsed());
+        conn
+    }
+
+    async fn async_connect(&self) -> Result<AnyAsyncConnection, ConnectionError> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let Some(backend) = self.backend() else {
+            return Err(unknown_scheme_error(self.url.as_str()));
+        };
+        let conn = match backend {
+            #[cfg(feature = "postgres")]
+            Backend::Postgres => AsyncPgConnection::establish(self.url.as_str()).await.map(AnyAsyncConnection::Postgres),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => SyncConnectionWrapper::<SqliteConnection>::establish(sqlite_path(self.url.as_str())).await.map(AnyAsyncConnection::Sqlite),
+            #[cfg(feature = "mysql")]
+            Backend::Mysql => AsyncMysqlConnection::establish(self.url.as_str()).await.map(AnyAsyncConnection::Mysql),
+            #[allow(unreachable_patterns)]
+            backend => Err(backend.disabled_error()),
+        };
+        #[cfg(feature = "metrics")]
+        crate::metrics::metrics().record_db_connect(backend.name(), conn.is_ok(), start.elapsed());
+        conn
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Backend;
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn accessors_match_only_their_backend() {
+        use diesel::{Connection, SqliteConnection};
+
+        let mut conn = super::AnyConnection::Sqlite(SqliteConnection::establish(":memory:").unwrap());
+        assert!(conn.as_sqlite_mut().is_some());
+        #[cfg(feature = "postgres")]
+        assert!(conn.as_pg_mut().is_none());
+        assert!(conn.into_sqlite().is_some());
     }
 
-    async fn async_connect(&self) -> Result<AsyncPgConnection, ConnectionError> {
-        AsyncPgConnection::establish(self.url.as_str()).await
+    #[test]
+    fn backend_from_url_scheme() {
+        assert_eq!(Backend::from_url("postgres://user:pw@localhost/bot"), Some(Backend::Postgres));
+        assert_eq!(Backend::from_url("postgresql://localhost/bot"), Some(Backend::Postgres));
+        assert_eq!(Backend::from_url("mysql://localhost/bot"), Some(Backend::Mysql));
+        assert_eq!(Backend::from_url("sqlite://bot.db"), Some(Backend::Sqlite));
+        assert_eq!(Backend::from_url("file:bot.db?mode=rwc"), Some(Backend::Sqlite));
+        assert_eq!(Backend::from_url("bot.db"), Some(Backend::Sqlite));
+        assert_eq!(Backend::from_url(":memory:"), Some(Backend::Sqlite));
+        assert_eq!(Backend::from_url("postgress://localhost/bot"), None);
+        assert_eq!(Backend::from_url("redis://localhost"), None);
     }
 }
@@ -0,0 +1,227 @@
+use std::{collections::HashMap, str::FromStr, sync::{Arc, RwLock}};
+
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serenity::all::GuildId;
+use tokio::sync::OnceCell;
+
+use super::{with_connection, AnyAsyncConnection, Connector, DbResult};
+
+diesel::table! {
+    azel_guild_settings (guild_id, key) {
+        guild_id -> BigInt,
+        // `key` is reserved in MySQL, so the column is named differently from the field.
+        #[sql_name = "setting_key"]
+        key -> Varchar,
+        value -> Text,
+    }
+}
+
+pub const CREATE_GUILD_SETTINGS_TABLE: &str = "CREATE TABLE IF NOT EXISTS azel_guild_settings (
+    guild_id BIGINT NOT NULL,
+    setting_key VARCHAR(64) NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (guild_id, setting_key)
+)";
+
+/// Snapshot of every setting stored for a single guild.
+#[derive(Debug, Clone, Default)]
+pub struct GuildSettings {
+    pub guild_id: GuildId,
+    values: HashMap<String, String>,
+}
+
+impl GuildSettings {
+    pub fn get_raw(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<Option<T>, T::Err> {
+        self.get_raw(key).map(str::parse).transpose()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// A guild's cached settings, and how many times they have been invalidated.
+#[derive(Default)]
+struct CacheSlot {
+    generation: u64,
+    settings: Option<Arc<GuildSettings>>,
+}
+
+/// Process-wide cache of guild settings, filled on first access and invalidated on writes.
+///
+/// Every invalidation bumps the guild's generation, and a load only fills the cache if the
+/// generation it started at is still current, so rows read before a write are never cached after it.
+#[derive(Default)]
+pub struct GuildSettingsCache {
+    table_ready: OnceCell<()>,
+    entries: RwLock<HashMap<GuildId, CacheSlot>>,
+}
+
+impl GuildSettingsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cached(&self, guild_id: GuildId) -> Option<Arc<GuildSettings>> {
+        self.entries.read().expect("settings cache lock poisoned").get(&guild_id).and_then(|slot| slot.settings.clone())
+    }
+
+    pub fn invalidate(&self, guild_id: GuildId) {
+        let mut entries = self.entries.write().expect("settings cache lock poisoned");
+        let slot = entries.entry(guild_id).or_default();
+        slot.generation += 1;
+        slot.settings = None;
+    }
+
+    pub fn clear(&self) {
+        for slot in self.entries.write().expect("settings cache lock poisoned").values_mut() {
+            slot.generation += 1;
+            slot.settings = None;
+        }
+    }
+
+    /// The guild's current generation, to pass to [`Self::insert`] once its rows are read.
+    fn generation(&self, guild_id: GuildId) -> u64 {
+        self.entries.write().expect("settings cache lock poisoned").entry(guild_id).or_default().generation
+    }
+
+    /// Caches `settings` unless the guild was invalidated since `generation` was read.
+    fn insert(&self, settings: GuildSettings, generation: u64) -> Arc<GuildSettings> {
+        let settings = Arc::new(settings);
+        let mut entries = self.entries.write().expect("settings cache lock poisoned");
+        let slot = entries.entry(settings.guild_id).or_default();
+        if slot.generation == generation {
+            slot.settings = Some(Arc::clone(&settings));
+        }
+        settings
+    }
+
+    async fn connect(&self, connector: impl Connector) -> DbResult<AnyAsyncConnection> {
+        let mut conn = connector.async_connect().await?;
+        self.table_ready.get_or_try_init(|| async {
+            with_connection!(AnyAsyncConnection, &mut conn, c => {
+                diesel::sql_query(CREATE_GUILD_SETTINGS_TABLE).execute(c).await.map(|_| ())
+            })
+        }).await?;
+        Ok(conn)
+    }
+
+    pub async fn load(&self, connector: impl Connector, guild_id: GuildId) -> DbResult<Arc<GuildSettings>> {
+        if let Some(settings) = self.cached(guild_id) {
+            return Ok(settings);
+        }
+
+        let generation = self.generation(guild_id);
+        let mut conn = self.connect(connector).await?;
+        let query = azel_guild_settings::table
+            .filter(azel_guild_settings::guild_id.eq(guild_id.get() as i64))
+            .select((azel_guild_settings::key, azel_guild_settings::value));
+        let rows: Vec<(String, String)> = with_connection!(AnyAsyncConnection, &mut conn, c => query.load(c).await)?;
+
+        Ok(self.insert(GuildSettings {
+            guild_id,
+            values: rows.into_iter().collect(),
+        }, generation))
+    }
+
+    pub async fn set(&self, connector: impl Connector, guild_id: GuildId, key: &str, value: &str) -> DbResult<()> {
+        let mut conn = self.connect(connector).await?;
+        let insert = diesel::insert_into(azel_guild_settings::table)
+            .values((
+                azel_guild_settings::guild_id.eq(guild_id.get() as i64),
+                azel_guild_settings::key.eq(key),
+                azel_guild_settings::value.eq(value),
+            ));
+        // Upserts are spelled differently per backend, so each connection gets its own conflict target.
+        match &mut conn {
+            #[cfg(feature = "postgres")]
+            AnyAsyncConnection::Postgres(c) => insert
+                .on_conflict((azel_guild_settings::guild_id, azel_guild_settings::key))
+                .do_update()
+                .set(azel_guild_settings::value.eq(value))
+                .execute(c)
+                .await?,
+            #[cfg(feature = "sqlite")]
+            AnyAsyncConnection::Sqlite(c) => insert
+                .on_conflict((azel_guild_settings::guild_id, azel_guild_settings::key))
+                .do_update()
+                .set(azel_guild_settings::value.eq(value))
+                .execute(c)
+                .await?,
+            #[cfg(feature = "mysql")]
+            AnyAsyncConnection::Mysql(c) => insert
+                .on_conflict(diesel::dsl::DuplicatedKeys)
+                .do_update()
+                .set(azel_guild_settings::value.eq(value))
+                .execute(c)
+                .await?,
+        };
+        self.invalidate(guild_id);
+        Ok(())
+    }
+
+    pub async fn reset(&self, connector: impl Connector, guild_id: GuildId, key: &str) -> DbResult<bool> {
+        let mut conn = self.connect(connector).await?;
+        let delete = diesel::delete(
+            azel_guild_settings::table
+                .filter(azel_guild_settings::guild_id.eq(guild_id.get() as i64))
+                .filter(azel_guild_settings::key.eq(key))
+        );
+        let removed = with_connection!(AnyAsyncConnection, &mut conn, c => delete.execute(c).await)?;
+        self.invalidate(guild_id);
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serenity::all::GuildId;
+
+    use super::{GuildSettings, GuildSettingsCache};
+
+    #[test]
+    fn typed_get_parses_stored_value() {
+        let settings = GuildSettings {
+            guild_id: GuildId::new(1),
+            values: HashMap::from([("log_channel".to_owned(), "1234".to_owned())]),
+        };
+        assert_eq!(settings.get::<u64>("log_channel"), Ok(Some(1234)));
+        assert_eq!(settings.get::<u64>("prefix"), Ok(None));
+        assert!(settings.get::<bool>("log_channel").is_err());
+    }
+
+    #[test]
+    fn invalidate_drops_cached_guild() {
+        let cache = GuildSettingsCache::new();
+        cache.insert(GuildSettings { guild_id: GuildId::new(1), values: HashMap::new() }, 0);
+        cache.insert(GuildSettings { guild_id: GuildId::new(2), values: HashMap::new() }, 0);
+        cache.invalidate(GuildId::new(1));
+        assert!(cache.cached(GuildId::new(1)).is_none());
+        assert!(cache.cached(GuildId::new(2)).is_some());
+    }
+
+    #[test]
+    fn load_racing_a_write_does_not_cache_stale_rows() {
+        let cache = GuildSettingsCache::new();
+        let guild_id = GuildId::new(1);
+        let generation = cache.generation(guild_id);
+        cache.invalidate(guild_id);
+        let stale = cache.insert(GuildSettings { guild_id, values: HashMap::new() }, generation);
+        assert_eq!(stale.guild_id, guild_id);
+        assert!(cache.cached(guild_id).is_none());
+
+        cache.insert(GuildSettings { guild_id, values: HashMap::new() }, cache.generation(guild_id));
+        assert!(cache.cached(guild_id).is_some());
+    }
+}
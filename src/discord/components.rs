@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use serenity::{all::{ButtonStyle, ComponentInteraction, ComponentInteractionCollector, CreateActionRow, CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage}, futures::StreamExt};
+
+use super::{attachment_failed, ExecutionContext, MessageContent, ReplyBuilder};
+use crate::cmd::RequestError;
+
+use tracing as trc;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pagination {
+    /// How long the buttons work after the first page is sent. They are disabled afterwards.
+    pub timeout: Duration,
+    /// Answers other users' clicks with a notice instead of turning the page.
+    pub invoker_only: bool,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            invoker_only: true,
+        }
+    }
+}
+
+fn page_buttons(id_prefix: &str, page: usize, pages: usize, enabled: bool) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{id_prefix}-prev"))
+            .label("Previous")
+            .style(ButtonStyle::Secondary)
+            .disabled(!enabled || page == 0),
+        CreateButton::new(format!("{id_prefix}-page"))
+            .label(format!("{} / {pages}", page + 1))
+            .style(ButtonStyle::Secondary)
+            .disabled(true),
+        CreateButton::new(format!("{id_prefix}-next"))
+            .label("Next")
+            .style(ButtonStyle::Secondary)
+            .disabled(!enabled || page + 1 == pages),
+    ])]
+}
+
+impl ExecutionContext<'_> {
+    /// Sends `pages` as one message with buttons to move between them, using [`Pagination::default`].
+    pub async fn reply_paginated(&self, pages: Vec<MessageContent>) -> Result<(), RequestError> {
+        self.reply_paginated_with(pages, Pagination::default()).await
+    }
+
+    /// Sends `pages` as one message with buttons to move between them, and returns once the buttons
+    /// have timed out.
+    pub async fn reply_paginated_with(&self, pages: Vec<MessageContent>, options: Pagination) -> Result<(), RequestError> {
+        let invoker = self.cmd.user.id;
+        let pages: Vec<ReplyBuilder> = pages.into_iter().map(|page| page.into_reply(invoker)).collect();
+        if pages.len() <= 1 {
+            let Some(page) = pages.into_iter().next() else {
+                return Err(RequestError::Internal("no pages to send".into()));
+            };
+            return self.send_reply(page.into()).await.map(|_| ());
+        }
+
+        let id_prefix = format!("azel-page-{}", self.cmd.id);
+        let with_buttons = |page: usize, enabled: bool| pages[page].clone().components(page_buttons(&id_prefix, page, pages.len(), enabled));
+
+        let mut page = 0;
+        let handle = self.send_reply(with_buttons(page, true).into()).await?;
+        let mut clicks = ComponentInteractionCollector::new(self.ctx)
+            .message_id(handle.message_id().await?)
+            .timeout(options.timeout)
+            .stream();
+        while let Some(click) = clicks.next().await {
+            if options.invoker_only && click.user.id != invoker {
+                self.refuse_click(&click).await;
+                continue;
+            }
+            page = match click.data.custom_id.strip_prefix(id_prefix.as_str()) {
+                Some("-prev") => page.saturating_sub(1),
+                Some("-next") => (page + 1).min(pages.len() - 1),
+                _ => page,
+            };
+            let update = with_buttons(page, true).to_response().await.map_err(attachment_failed)?;
+            if let Err(e) = click.create_response(self.ctx, CreateInteractionResponse::UpdateMessage(update)).await {
+                trc::warn!(err = ?e, "PAGE-UPDATE-FAILED");
+            }
+        }
+
+        handle.edit(with_buttons(page, false).into()).await
+    }
+
+    /// Asks the invoker to confirm with Yes/No buttons, returning whether they chose Yes.
+    ///
+    /// The prompt is updated to show the choice. If nobody answers within `timeout`, the buttons are
+    /// removed and a user error is returned.
+    pub async fn confirm(&self, prompt: impl Into<String>, timeout: Duration) -> Result<bool, RequestError> {
+        let invoker = self.cmd.user.id;
+        let prompt = prompt.into();
+        let yes_id = format!("azel-confirm-{}-yes", self.cmd.id);
+        let no_id = format!("azel-confirm-{}-no", self.cmd.id);
+        let with_outcome = |outcome: &str| ReplyBuilder::new()
+            .content(format!("{prompt}\n{outcome}"))
+            .restrict_mentions_to(invoker)
+            .components(vec![]);
+
+        let buttons = vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(yes_id.as_str()).label("Yes").style(ButtonStyle::Danger),
+            CreateButton::new(no_id.as_str()).label("No").style(ButtonStyle::Secondary),
+        ])];
+        let handle = self.send_reply(ReplyBuilder::new().content(prompt.as_str()).restrict_mentions_to(invoker).components(buttons).into()).await?;
+        let mut clicks = ComponentInteractionCollector::new(self.ctx)
+            .message_id(handle.message_id().await?)
+            .custom_ids(vec![yes_id.clone(), no_id])
+            .timeout(timeout)
+            .stream();
+        while let Some(click) = clicks.next().await {
+            if click.user.id != invoker {
+                self.refuse_click(&click).await;
+                continue;
+            }
+            let confirmed = click.data.custom_id == yes_id;
+            let update = with_outcome(if confirmed { "Confirmed." } else { "Cancelled." }).to_response().await.map_err(attachment_failed)?;
+            if let Err(e) = click.create_response(self.ctx, CreateInteractionResponse::UpdateMessage(update)).await {
+                trc::warn!(err = ?e, "CONFIRM-UPDATE-FAILED");
+            }
+            return Ok(confirmed);
+        }
+
+        handle.edit(with_outcome("Timed out.").into()).await?;
+        Err(RequestError::User("No answer was given in time.".into()))
+    }
+
+    /// Tells a user clicking someone else's buttons that they are not theirs.
+    async fn refuse_click(&self, click: &ComponentInteraction) {
+        let notice = CreateInteractionResponseMessage::new()
+            .content(format!("Only <@{}> can use these buttons.", self.cmd.user.id))
+            .allowed_mentions(Default::default())
+            .ephemeral(true);
+        if let Err(e) = click.create_response(self.ctx, CreateInteractionResponse::Message(notice)).await {
+            trc::warn!(err = ?e, "CLICK-REFUSE-FAILED");
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use std::{future::Future, sync::Mutex, time::Duration};
+#[cfg(feature = "database")]
+use std::{str::FromStr, sync::Arc};
+
+use serenity::{all::{ChannelType, CommandInteraction, GuildChannel, GuildId, MessageId}, builder::{CreateEmbed, CreateInteractionResponse}, client::Context};
+
+use crate::{cmd::{ErrorDetail, ErrorReporter, RequestError}, ops::OpsChannel};
+#[cfg(feature = "database")]
+use crate::{db::settings::{GuildSettings, GuildSettingsCache}, DatabaseConfiguration};
+
+use tracing as trc;
+
+mod components;
+mod reply;
+
+pub use components::Pagination;
+pub use reply::{Attachment, MessageContent, Overflow, ReplyBuilder, MESSAGE_LIMIT};
+
+/// Discord drops an interaction that gets no initial response within 3 seconds. Deferring a little
+/// earlier leaves room for the defer request itself.
+pub const AUTO_DEFER_AFTER: Duration = Duration::from_millis(2500);
+
+/// How a command is deferred when it has not replied within [`AUTO_DEFER_AFTER`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AutoDefer {
+    /// Defers with a placeholder everyone in the channel can see.
+    #[default]
+    Public,
+    /// Defers with a placeholder only the invoker can see, and keeps followups ephemeral.
+    Ephemeral,
+    /// Never defers; the command replies or defers in time itself.
+    Off,
+}
+
+/// How far the initial response to a command has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResponseState {
+    /// Nothing has been sent yet.
+    #[default]
+    Pending,
+    /// A defer left a "thinking" placeholder, which the first followup replaces.
+    Deferred { ephemeral: bool },
+    /// The initial response has been sent, or the placeholder replaced.
+    Sent { ephemeral_followups: bool },
+}
+
+/// How [`ExecutionContext::send_reply`] sends one message in a given [`ResponseState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendPlan {
+    Initial,
+    Followup,
+    /// The followup would replace a public placeholder and lose its ephemeral flag, so the
+    /// placeholder is deleted first and the followup sent as a message of its own.
+    DeletePlaceholderThenFollowup,
+}
+
+impl ResponseState {
+    fn plan(self, ephemeral: bool) -> SendPlan {
+        match self {
+            Self::Pending => SendPlan::Initial,
+            Self::Deferred { ephemeral: false } if ephemeral => SendPlan::DeletePlaceholderThenFollowup,
+            Self::Deferred { .. } | Self::Sent { .. } => SendPlan::Followup,
+        }
+    }
+
+    /// Whether followups are forced ephemeral, after [`ExecutionContext::defer_ephemeral`].
+    fn ephemeral_followups(self) -> bool {
+        matches!(self, Self::Deferred { ephemeral: true } | Self::Sent { ephemeral_followups: true })
+    }
+
+    /// The state once a message has been sent in this one.
+    fn after_send(self) -> Self {
+        Self::Sent { ephemeral_followups: self.ephemeral_followups() }
+    }
+}
+
+/// Tracks the initial response to a command, and keeps followups from overtaking it.
+///
+/// The state lock is only held to read or change the state, never across a request to Discord.
+#[derive(Default)]
+pub struct ResponseSlot {
+    state: Mutex<ResponseState>,
+    /// Held while the initial response, a defer, or the followup replacing a defer's placeholder is
+    /// being sent, so later sends wait for it.
+    sending: tokio::sync::Mutex<()>,
+}
+
+/// A send claimed through [`ResponseSlot::begin_send`].
+struct SendTicket<'a> {
+    plan: SendPlan,
+    ephemeral_followups: bool,
+    /// Held back from other sends until this one is done, unless it is a plain followup.
+    _sending: Option<tokio::sync::MutexGuard<'a, ()>>,
+}
+
+impl ResponseSlot {
+    pub fn state(&self) -> ResponseState {
+        *self.state.lock().expect("response state lock poisoned")
+    }
+
+    fn update(&self, update: impl FnOnce(ResponseState) -> ResponseState) {
+        let mut state = self.state.lock().expect("response state lock poisoned");
+        *state = update(*state);
+    }
+
+    /// Waits for any initial response in flight, then plans the next send. The state only moves on
+    /// once [`Self::commit_send`] is called, which the ticket's guard keeps anyone else from racing.
+    async fn begin_send(&self, ephemeral: bool) -> SendTicket<'_> {
+        let sending = self.sending.lock().await;
+        let state = self.state();
+        SendTicket {
+            plan: state.plan(ephemeral),
+            ephemeral_followups: state.ephemeral_followups(),
+            _sending: (!matches!(state, ResponseState::Sent { .. })).then_some(sending),
+        }
+    }
+
+    fn commit_send(&self) {
+        self.update(ResponseState::after_send);
+    }
+
+    /// Claims the initial response for a defer, waiting for any send in flight. `None` if a
+    /// response has already been sent.
+    async fn begin_defer(&self, ephemeral: bool) -> Option<tokio::sync::MutexGuard<'_, ()>> {
+        let sending = self.sending.lock().await;
+        self.claim_defer(sending, ephemeral)
+    }
+
+    /// Like [`Self::begin_defer`], but gives up instead of waiting if a send is in flight.
+    fn try_begin_defer(&self, ephemeral: bool) -> Option<tokio::sync::MutexGuard<'_, ()>> {
+        let sending = self.sending.try_lock().ok()?;
+        self.claim_defer(sending, ephemeral)
+    }
+
+    fn claim_defer<'a>(&self, sending: tokio::sync::MutexGuard<'a, ()>, ephemeral: bool) -> Option<tokio::sync::MutexGuard<'a, ()>> {
+        let mut state = self.state.lock().expect("response state lock poisoned");
+        if *state != ResponseState::Pending {
+            return None;
+        }
+        *state = ResponseState::Deferred { ephemeral };
+        Some(sending)
+    }
+}
+
+/// Drives `work`, running `defer` alongside it if no response has been started within `after`.
+///
+/// `work` keeps being polled while the defer is sent, so a send it has in flight can finish.
+async fn drive_with_auto_defer<F, D>(slot: &ResponseSlot, after: Duration, ephemeral: bool, work: F, defer: impl FnOnce() -> D) -> F::Output
+where
+    F: Future,
+    D: Future<Output = Result<(), RequestError>>,
+{
+    let mut work = std::pin::pin!(work);
+    tokio::select! {
+        output = &mut work => return output,
+        () = tokio::time::sleep(after) => {},
+    }
+    let Some(sending) = slot.try_begin_defer(ephemeral) else {
+        return work.await;
+    };
+    trc::info!(after_ms = after.as_millis(), ephemeral, "REQ-AUTO-DEFER");
+    let defer = async move {
+        let deferred = defer().await;
+        drop(sending);
+        deferred
+    };
+    let (output, deferred) = tokio::join!(work, defer);
+    if let Err(e) = deferred {
+        trc::warn!(err = ?e, "REQ-AUTO-DEFER-FAIL");
+    }
+    output
+}
+
+pub struct ExecutionContext<'a> {
+    #[cfg(feature = "database")]
+    pub db_cfg: Option<&'a DatabaseConfiguration>,
+    #[cfg(feature = "database")]
+    pub settings_cache: &'a GuildSettingsCache,
+    pub cmd: &'a CommandInteraction,
+    pub ctx: &'a Context,
+    /// Presents errors returned by the command to the invoker.
+    pub error_reporter: &'a dyn ErrorReporter,
+    pub ops_channel: Option<&'a OpsChannel>,
+    pub response: ResponseSlot,
+}
+
+/// Which message a [`ResponseHandle`] points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseTarget {
+    /// The initial response, whether a reply or a defer.
+    Original,
+    Followup(MessageId),
+}
+
+/// A message sent through [`ExecutionContext::send_reply`], for editing or deleting it later.
+pub struct ResponseHandle<'a> {
+    exec: &'a ExecutionContext<'a>,
+    pub target: ResponseTarget,
+}
+
+impl ResponseHandle<'_> {
+    /// The id of the message, fetched from Discord for the initial response.
+    pub async fn message_id(&self) -> Result<MessageId, RequestError> {
+        match self.target {
+            ResponseTarget::Original => self.exec.cmd.get_response(&self.exec.ctx.http).await.map(|message| message.id).map_err(|e| {
+                RequestError::Internal(ErrorDetail::new("Message failed to load.").with_source(e))
+            }),
+            ResponseTarget::Followup(message_id) => Ok(message_id),
+        }
+    }
+
+    /// Replaces the message with `content`, e.g. to show progress on a long-running command.
+    pub async fn edit(&self, content: MessageContent) -> Result<(), RequestError> {
+        match self.target {
+            ResponseTarget::Original => self.exec.edit_original_response(content).await,
+            ResponseTarget::Followup(message_id) => {
+                let followup = content.into_reply(self.exec.cmd.user.id).to_followup().await.map_err(attachment_failed)?;
+                self.exec.cmd.edit_followup(&self.exec.ctx, message_id, followup).await.map(|_| ()).map_err(edit_failed)
+            },
+        }
+    }
+
+    pub async fn delete(self) -> Result<(), RequestError> {
+        let deleted = match self.target {
+            ResponseTarget::Original => self.exec.cmd.delete_response(&self.exec.ctx).await,
+            ResponseTarget::Followup(message_id) => self.exec.cmd.delete_followup(&self.exec.ctx, message_id).await,
+        };
+        deleted.map_err(|e| RequestError::Internal(ErrorDetail::new("Message failed to delete.").with_source(e)))
+    }
+}
+
+impl<'a> ExecutionContext<'a> {
+    pub async fn reply(&self, content: String) -> Result<(), RequestError> {
+        self.send_reply(MessageContent::Simple(content)).await.map(|_| ())
+    }
+
+    pub async fn reply_restricted(&self, content: String) -> Result<(), RequestError> {
+        self.send_reply(MessageContent::SimpleRestrictedMention(content)).await.map(|_| ())
+    }
+
+    /// A reply only the invoker can see, which cannot ping anyone else.
+    pub async fn reply_ephemeral(&self, content: String) -> Result<(), RequestError> {
+        self.send_reply(MessageContent::SimpleRestrictedMention(content).ephemeral()).await.map(|_| ())
+    }
+
+    pub async fn reply_embed(&self, embed: CreateEmbed) -> Result<(), RequestError> {
+        self.send_reply(embed.into()).await.map(|_| ())
+    }
+
+    /// Sends `content` as the initial response, or as a followup once a response has been sent.
+    ///
+    /// Text over Discord's limit is split over several messages or attached as a file, as the
+    /// reply's [`Overflow`] says. The handle points at the first message.
+    pub async fn send_reply(&self, content: MessageContent) -> Result<ResponseHandle<'_>, RequestError> {
+        let (reply, continuations) = content.into_reply(self.cmd.user.id).fit_content();
+        let handle = self.send_one(reply).await?;
+        for continuation in continuations {
+            self.send_one(continuation).await?;
+        }
+        Ok(handle)
+    }
+
+    async fn send_one(&self, reply: ReplyBuilder) -> Result<ResponseHandle<'_>, RequestError> {
+        let ticket = self.response.begin_send(reply.is_ephemeral()).await;
+        let sent = if ticket.plan == SendPlan::Initial {
+            let message = reply.to_response().await.map_err(attachment_failed)?;
+            self.response.commit_send();
+            self.cmd.create_response(&self.ctx, CreateInteractionResponse::Message(message)).await.map(|()| ResponseTarget::Original)
+        } else {
+            let reply = if ticket.ephemeral_followups { reply.ephemeral(true) } else { reply };
+            let followup = reply.to_followup().await.map_err(attachment_failed)?;
+            self.response.commit_send();
+            if ticket.plan == SendPlan::DeletePlaceholderThenFollowup {
+                trc::debug!("REQ-PLACEHOLDER-DELETE");
+                self.cmd.delete_response(&self.ctx).await.map_err(send_failed)?;
+            }
+            self.cmd.create_followup(&self.ctx, followup).await.map(|message| ResponseTarget::Followup(message.id))
+        };
+        drop(ticket);
+        sent.map(|target| ResponseHandle { exec: self, target }).map_err(send_failed)
+    }
+
+    /// Replaces the initial response, including the placeholder left by a defer.
+    pub async fn edit_original_response(&self, content: MessageContent) -> Result<(), RequestError> {
+        let edit = content.into_reply(self.cmd.user.id).to_edit().await.map_err(attachment_failed)?;
+        let _sending = self.response.sending.lock().await;
+        self.cmd.edit_response(&self.ctx, edit).await.map_err(edit_failed)?;
+        self.response.update(|state| match state {
+            ResponseState::Deferred { .. } => state.after_send(),
+            state => state,
+        });
+        Ok(())
+    }
+
+    /// Drives `work`, deferring the response as `mode` says if it has not replied within `after`.
+    pub async fn with_auto_defer<F: Future>(&self, after: Duration, mode: AutoDefer, work: F) -> F::Output {
+        let ephemeral = match mode {
+            AutoDefer::Public => false,
+            AutoDefer::Ephemeral => true,
+            AutoDefer::Off => return work.await,
+        };
+        drive_with_auto_defer(&self.response, after, ephemeral, work, || self.send_defer(ephemeral)).await
+    }
+
+    pub async fn defer(&self) -> Result<(), RequestError> {
+        self.defer_with(false).await
+    }
+
+    /// Defers with a response only the invoker can see, and keeps later followups ephemeral too.
+    pub async fn defer_ephemeral(&self) -> Result<(), RequestError> {
+        self.defer_with(true).await
+    }
+
+    async fn defer_with(&self, ephemeral: bool) -> Result<(), RequestError> {
+        let Some(_sending) = self.response.begin_defer(ephemeral).await else {
+            return Ok(());
+        };
+        self.send_defer(ephemeral).await
+    }
+
+    async fn send_defer(&self, ephemeral: bool) -> Result<(), RequestError> {
+        let deferred = if ephemeral { self.cmd.defer_ephemeral(&self.ctx).await } else { self.cmd.defer(&self.ctx).await };
+        deferred.map_err(send_failed)
+    }
+}
+
+fn send_failed(e: serenity::Error) -> RequestError {
+    RequestError::Internal(ErrorDetail::new("Message failed to send.").with_source(e))
+}
+
+fn attachment_failed(e: serenity::Error) -> RequestError {
+    RequestError::Internal(ErrorDetail::new("Attachment failed to load.").with_source(e))
+}
+
+fn edit_failed(e: serenity::Error) -> RequestError {
+    RequestError::Internal(ErrorDetail::new("Message failed to edit.").with_source(e))
+}
+
+impl ExecutionContext<'_> {
+    pub async fn find_interactor_voice_channel(&self, guild_id: GuildId) -> Result<GuildChannel, RequestError> {
+        let voice_channels = guild_id.channels(self.ctx).await.map_err(|e| RequestError::Internal(ErrorDetail::new("channels failed to load").with_source(e)))?.into_values().filter(|ch| ch.kind == ChannelType::Voice);
+        let mut located_channel = None;
+        for channel in voice_channels {
+            let joined_members = channel.members(self.ctx).map_err(|e| RequestError::Internal(ErrorDetail::new("channel members failed to load").with_source(e)))?;
+            if joined_members.iter().any(|j| j.user.id == self.cmd.user.id) {
+                located_channel = Some(channel);
+                break;
+            }
+        }
+        located_channel.ok_or_else(|| RequestError::User("if no provided channel, caller must be in a voice channel".into()))
+    }
+}
+
+#[cfg(feature = "database")]
+impl ExecutionContext<'_> {
+    /// The database configuration, or an internal error if the bot was started without one.
+    pub fn db(&self) -> Result<&DatabaseConfiguration, RequestError> {
+        self.db_cfg.ok_or_else(|| {
+            trc::error!("DB-NOT-CONFIGURED");
+            RequestError::Internal("database not configured".into())
+        })
+    }
+
+    fn settings_guild_id(&self) -> Result<GuildId, RequestError> {
+        self.cmd.guild_id.ok_or_else(|| RequestError::User("This command can only be used in a server.".into()))
+    }
+
+    pub async fn guild_settings(&self) -> Result<Arc<GuildSettings>, RequestError> {
+        let guild_id = self.settings_guild_id()?;
+        self.settings_cache.load(self.db()?, guild_id).await.map_err(|e| {
+            RequestError::Internal(ErrorDetail::new(format!("settings for guild {guild_id} failed to load")).with_source(e))
+        })
+    }
+
+    pub async fn guild_setting<T: FromStr>(&self, key: &str) -> Result<Option<T>, RequestError> {
+        let settings = self.guild_settings().await?;
+        settings.get(key).map_err(|_e| {
+            RequestError::Internal(format!("setting `{key}` for guild {} failed to parse", settings.guild_id).into())
+        })
+    }
+
+    pub async fn set_guild_setting(&self, key: &str, value: &str) -> Result<(), RequestError> {
+        let guild_id = self.settings_guild_id()?;
+        self.settings_cache.set(self.db()?, guild_id, key, value).await.map_err(|e| {
+            RequestError::Internal(ErrorDetail::new(format!("setting `{key}` for guild {guild_id} failed to save")).with_source(e))
+        })
+    }
+
+    pub async fn reset_guild_setting(&self, key: &str) -> Result<bool, RequestError> {
+        let guild_id = self.settings_guild_id()?;
+        self.settings_cache.reset(self.db()?, guild_id, key).await.map_err(|e| {
+            RequestError::Internal(ErrorDetail::new(format!("setting `{key}` for guild {guild_id} failed to reset")).with_source(e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::atomic::{AtomicBool, Ordering}, time::Duration};
+
+    use tokio::time::Instant;
+
+    use super::{drive_with_auto_defer, ResponseSlot, ResponseState, SendPlan};
+
+    const AFTER: Duration = Duration::from_millis(2500);
+
+    #[tokio::test(start_paused = true)]
+    async fn send_in_flight_at_the_deadline_is_not_deferred() {
+        let slot = ResponseSlot::default();
+        let deferred = AtomicBool::new(false);
+        let work = async {
+            let ticket = slot.begin_send(false).await;
+            assert_eq!(ticket.plan, SendPlan::Initial);
+            slot.commit_send();
+            // The initial response is still being sent when the deadline passes.
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            drop(ticket);
+            7
+        };
+        let output = drive_with_auto_defer(&slot, AFTER, false, work, || async {
+            deferred.store(true, Ordering::Relaxed);
+            Ok(())
+        }).await;
+        assert_eq!(output, 7);
+        assert!(!deferred.load(Ordering::Relaxed));
+        assert_eq!(slot.state(), ResponseState::Sent { ephemeral_followups: false });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reply_during_auto_defer_waits_for_it_and_stays_ephemeral() {
+        let slot = ResponseSlot::default();
+        let start = Instant::now();
+        let work = async {
+            tokio::time::sleep(Duration::from_secs(3)).await;
+            let ticket = slot.begin_send(true).await;
+            (ticket.plan, start.elapsed())
+        };
+        let (plan, sent_at) = drive_with_auto_defer(&slot, AFTER, false, work, || async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(())
+        }).await;
+        assert_eq!(plan, SendPlan::DeletePlaceholderThenFollowup);
+        assert!(sent_at >= AFTER + Duration::from_secs(1), "{sent_at:?}");
+    }
+
+    #[test]
+    fn ephemeral_reply_after_public_auto_defer_stays_ephemeral() {
+        let deferred = ResponseState::Deferred { ephemeral: false };
+        assert_eq!(deferred.plan(true), SendPlan::DeletePlaceholderThenFollowup);
+        assert_eq!(deferred.plan(false), SendPlan::Followup);
+        assert!(!deferred.after_send().ephemeral_followups());
+
+        let deferred = ResponseState::Deferred { ephemeral: true };
+        assert_eq!(deferred.plan(true), SendPlan::Followup);
+        assert!(deferred.after_send().ephemeral_followups());
+
+        assert_eq!(ResponseState::Pending.plan(true), SendPlan::Initial);
+        assert_eq!(ResponseState::Sent { ephemeral_followups: false }.plan(true), SendPlan::Followup);
+    }
+}
@@ -0,0 +1,400 @@
+use std::path::PathBuf;
+
+use serenity::{all::{CreateInteractionResponseFollowup, UserId}, builder::{CreateActionRow, CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateInteractionResponseMessage, EditInteractionResponse}};
+
+/// Most characters Discord accepts in a message's text.
+pub const MESSAGE_LIMIT: usize = 2000;
+/// Name of the file oversized text is sent as under [`Overflow::Attach`].
+const OVERFLOW_FILENAME: &str = "message.txt";
+/// Closes a code block left open at the end of a split message.
+const FENCE_CLOSE: &str = "\n```";
+
+/// What to do with text over [`MESSAGE_LIMIT`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Sends the text over several messages, split on line and code-block boundaries.
+    #[default]
+    Split,
+    /// Sends the text as a file attachment instead.
+    Attach,
+}
+
+#[derive(Debug, Clone)]
+pub enum Attachment {
+    /// In-memory data, e.g. a generated CSV export or image.
+    Bytes { data: Vec<u8>, filename: String },
+    /// A file read from disk when the reply is sent, named after its last path component.
+    Path(PathBuf),
+}
+
+impl Attachment {
+    pub fn bytes(data: impl Into<Vec<u8>>, filename: impl Into<String>) -> Self {
+        Self::Bytes { data: data.into(), filename: filename.into() }
+    }
+
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        Self::Path(path.into())
+    }
+
+    async fn load(&self) -> serenity::Result<CreateAttachment> {
+        match self {
+            Self::Bytes { data, filename } => Ok(CreateAttachment::bytes(data.as_slice(), filename.as_str())),
+            Self::Path(path) => CreateAttachment::path(path).await,
+        }
+    }
+}
+
+/// A reply assembled once and sent as either the initial interaction response or a followup.
+#[derive(Debug, Clone, Default)]
+pub struct ReplyBuilder {
+    content: Option<String>,
+    embeds: Vec<CreateEmbed>,
+    allowed_mentions: Option<CreateAllowedMentions>,
+    ephemeral: bool,
+    attachments: Vec<Attachment>,
+    components: Option<Vec<CreateActionRow>>,
+    overflow: Overflow,
+}
+
+impl ReplyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    /// Adds an embed. Discord shows up to 10 per message.
+    pub fn embed(mut self, embed: CreateEmbed) -> Self {
+        self.embeds.push(embed);
+        self
+    }
+
+    pub fn embeds(mut self, embeds: impl IntoIterator<Item = CreateEmbed>) -> Self {
+        self.embeds.extend(embeds);
+        self
+    }
+
+    pub fn allowed_mentions(mut self, allowed_mentions: CreateAllowedMentions) -> Self {
+        self.allowed_mentions = Some(allowed_mentions);
+        self
+    }
+
+    /// Shows the reply only to the invoker.
+    pub fn ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
+    }
+
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    pub fn attachments(mut self, attachments: impl IntoIterator<Item = Attachment>) -> Self {
+        self.attachments.extend(attachments);
+        self
+    }
+
+    /// Rows of buttons or select menus. Clicks arrive as component interactions.
+    pub fn components(mut self, components: Vec<CreateActionRow>) -> Self {
+        self.components = Some(components);
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Applies the overflow behaviour to text over [`MESSAGE_LIMIT`], returning the reply to send
+    /// first and the followups to send after it. Embeds and attachments stay on the first reply.
+    pub fn fit_content(mut self) -> (Self, Vec<Self>) {
+        let Some(content) = self.content.take_if(|content| content.chars().count() > MESSAGE_LIMIT) else {
+            return (self, vec![]);
+        };
+        match self.overflow {
+            Overflow::Attach => (self.attachment(Attachment::bytes(content, OVERFLOW_FILENAME)), vec![]),
+            Overflow::Split => {
+                let mut chunks = split_content(&content, MESSAGE_LIMIT).into_iter();
+                self.content = chunks.next();
+                let rest = chunks.map(|chunk| Self {
+                    content: Some(chunk),
+                    allowed_mentions: self.allowed_mentions.clone(),
+                    ephemeral: self.ephemeral,
+                    ..Self::default()
+                }).collect();
+                (self, rest)
+            },
+        }
+    }
+
+    /// Only lets `user` be pinged by the reply.
+    pub fn restrict_mentions_to(self, user: UserId) -> Self {
+        self.allowed_mentions(CreateAllowedMentions::new().users([user]))
+    }
+
+    async fn load_attachments(&self) -> serenity::Result<Vec<CreateAttachment>> {
+        let mut files = Vec::with_capacity(self.attachments.len());
+        for attachment in &self.attachments {
+            files.push(attachment.load().await?);
+        }
+        Ok(files)
+    }
+
+    /// Builds the initial response. Fails if an attachment cannot be read from disk.
+    pub async fn to_response(&self) -> serenity::Result<CreateInteractionResponseMessage> {
+        let mut builder = CreateInteractionResponseMessage::new()
+            .embeds(self.embeds.clone())
+            .ephemeral(self.ephemeral)
+            .files(self.load_attachments().await?);
+        if let Some(content) = &self.content {
+            builder = builder.content(content.as_str());
+        }
+        if let Some(allowed_mentions) = &self.allowed_mentions {
+            builder = builder.allowed_mentions(allowed_mentions.clone());
+        }
+        if let Some(components) = &self.components {
+            builder = builder.components(components.clone());
+        }
+        Ok(builder)
+    }
+
+    /// Builds a followup. Fails if an attachment cannot be read from disk.
+    pub async fn to_followup(&self) -> serenity::Result<CreateInteractionResponseFollowup> {
+        let mut builder = CreateInteractionResponseFollowup::new()
+            .embeds(self.embeds.clone())
+            .ephemeral(self.ephemeral)
+            .files(self.load_attachments().await?);
+        if let Some(content) = &self.content {
+            builder = builder.content(content.as_str());
+        }
+        if let Some(allowed_mentions) = &self.allowed_mentions {
+            builder = builder.allowed_mentions(allowed_mentions.clone());
+        }
+        if let Some(components) = &self.components {
+            builder = builder.components(components.clone());
+        }
+        Ok(builder)
+    }
+
+    /// Builds an edit of the original response, replacing its text, embeds and attachments.
+    ///
+    /// A response cannot be made ephemeral or public after it is sent, so the flag is ignored.
+    pub async fn to_edit(&self) -> serenity::Result<EditInteractionResponse> {
+        let mut builder = EditInteractionResponse::new().embeds(self.embeds.clone()).clear_attachments();
+        for file in self.load_attachments().await? {
+            builder = builder.new_attachment(file);
+        }
+        if let Some(content) = &self.content {
+            builder = builder.content(content.as_str());
+        }
+        if let Some(allowed_mentions) = &self.allowed_mentions {
+            builder = builder.allowed_mentions(allowed_mentions.clone());
+        }
+        if let Some(components) = &self.components {
+            builder = builder.components(components.clone());
+        }
+        Ok(builder)
+    }
+}
+
+pub enum MessageContent {
+    Simple(String),
+    SimpleRestrictedMention(String),
+    /// A single embed, built with serenity's `CreateEmbed` (title, fields, colour, footer, images, timestamp).
+    Embed(Box<CreateEmbed>),
+    /// Anything else, e.g. text alongside several embeds.
+    Reply(ReplyBuilder),
+    /// Shows the wrapped content only to the invoker.
+    Ephemeral(Box<MessageContent>),
+    /// The wrapped content with files attached.
+    WithAttachments(Box<MessageContent>, Vec<Attachment>),
+}
+
+impl MessageContent {
+    /// `user` is the invoker, the only user a restricted reply may ping.
+    pub fn into_reply(self, user: UserId) -> ReplyBuilder {
+        match self {
+            Self::Simple(s) => ReplyBuilder::new().content(s),
+            Self::SimpleRestrictedMention(s) => ReplyBuilder::new().content(s).restrict_mentions_to(user),
+            Self::Embed(embed) => ReplyBuilder::new().embed(*embed),
+            Self::Reply(reply) => reply,
+            Self::Ephemeral(content) => content.into_reply(user).ephemeral(true),
+            Self::WithAttachments(content, attachments) => content.into_reply(user).attachments(attachments),
+        }
+    }
+
+    /// A lone file with no text.
+    pub fn file(attachment: Attachment) -> Self {
+        Self::Reply(ReplyBuilder::new().attachment(attachment))
+    }
+
+    pub fn with_attachments(self, attachments: impl IntoIterator<Item = Attachment>) -> Self {
+        Self::WithAttachments(Box::new(self), attachments.into_iter().collect())
+    }
+
+    pub fn ephemeral(self) -> Self {
+        Self::Ephemeral(Box::new(self))
+    }
+}
+
+impl From<ReplyBuilder> for MessageContent {
+    fn from(reply: ReplyBuilder) -> Self {
+        Self::Reply(reply)
+    }
+}
+
+impl From<CreateEmbed> for MessageContent {
+    fn from(embed: CreateEmbed) -> Self {
+        Self::Embed(Box::new(embed))
+    }
+}
+
+/// Accumulates the pieces of [`split_content`].
+struct Splitter {
+    chunks: Vec<String>,
+    chunk: String,
+    chunk_len: usize,
+    /// The opening line of the code block the split has reached, if inside one.
+    fence: Option<String>,
+    /// Bytes at the start of `chunk` spent reopening `fence`.
+    reopened_len: usize,
+}
+
+impl Splitter {
+    /// Whether the piece holds more than whitespace and code block fences, and so is worth sending
+    /// on its own.
+    fn has_text(&self) -> bool {
+        self.chunk[self.reopened_len..].lines().map(str::trim).any(|line| !line.is_empty() && !line.starts_with("```"))
+    }
+
+    fn push(&mut self, text: &str) {
+        self.chunk.push_str(text);
+        self.chunk_len += text.chars().count();
+    }
+
+    /// Starts the next piece, reopening the code block the split is in.
+    fn reset(&mut self) {
+        self.chunk = self.fence.as_ref().map(|fence| format!("{fence}\n")).unwrap_or_default();
+        self.chunk_len = self.chunk.chars().count();
+        self.reopened_len = self.chunk.len();
+    }
+
+    /// Ends the current piece, closing and reopening any code block it cuts.
+    fn flush(&mut self) {
+        let has_text = self.has_text();
+        let mut chunk = std::mem::take(&mut self.chunk);
+        if let Some(fence) = &self.fence {
+            let opening = format!("{fence}\n");
+            // A block opened on the last line moves to the next piece whole instead of being cut empty.
+            if chunk.ends_with(&opening) && chunk.len() > opening.len() {
+                chunk.truncate(chunk.len() - opening.len());
+            } else {
+                if !chunk.ends_with('\n') {
+                    chunk.push('\n');
+                }
+                chunk.push_str("```");
+            }
+        }
+        if has_text {
+            self.chunks.push(chunk);
+        }
+        self.reset();
+    }
+}
+
+/// Splits `content` into pieces of at most `limit` characters, breaking between lines where possible.
+///
+/// A code block cut by a break is closed at the end of one piece and reopened, with its language,
+/// at the start of the next. Lines longer than a piece are cut mid-line. A piece is never only
+/// whitespace and fences: such lines are carried into the next piece, or dropped if no text follows.
+pub fn split_content(content: &str, limit: usize) -> Vec<String> {
+    let mut splitter = Splitter { chunks: vec![], chunk: String::new(), chunk_len: 0, fence: None, reopened_len: 0 };
+    for line in content.split_inclusive('\n') {
+        let toggles_fence = line.trim_start().starts_with("```");
+        let mut rest = line;
+        while !rest.is_empty() {
+            let open_after = splitter.fence.is_some() != toggles_fence;
+            let reserve = if open_after { FENCE_CLOSE.len() } else { 0 };
+            let room = limit.saturating_sub(splitter.chunk_len + reserve);
+            if rest.chars().count() <= room {
+                splitter.push(rest);
+                break;
+            }
+            if splitter.has_text() {
+                splitter.flush();
+            } else if (room == 0 || toggles_fence) && splitter.chunk.len() > splitter.reopened_len {
+                splitter.reset();
+            } else {
+                let cut = rest.char_indices().nth(room.max(1)).map_or(rest.len(), |(i, _)| i);
+                splitter.push(&rest[..cut]);
+                rest = &rest[cut..];
+                splitter.flush();
+            }
+        }
+        if toggles_fence {
+            splitter.fence = match splitter.fence {
+                Some(_) => None,
+                None => Some(line.trim().to_owned()),
+            };
+        }
+    }
+    if splitter.has_text() {
+        splitter.chunks.push(splitter.chunk);
+    }
+    splitter.chunks
+}
+
+#[cfg(test)]
+mod test {
+    use serenity::{all::UserId, builder::CreateEmbed};
+
+    use super::{split_content, Attachment, MessageContent};
+
+    #[tokio::test]
+    async fn response_and_followup_carry_the_same_message() {
+        let embed = CreateEmbed::new().title("Leaderboard").field("first", "azel", true).colour(0x00ff00);
+        let reply = MessageContent::SimpleRestrictedMention("hi <@1>".to_owned())
+            .ephemeral()
+            .with_attachments([Attachment::bytes("a,b\n1,2\n", "export.csv")])
+            .into_reply(UserId::new(1))
+            .embed(embed);
+
+        let response = serde_json::to_value(reply.to_response().await.unwrap()).unwrap();
+        let followup = serde_json::to_value(reply.to_followup().await.unwrap()).unwrap();
+        for field in ["content", "embeds", "allowed_mentions", "flags", "attachments"] {
+            assert_eq!(response[field], followup[field], "{field}");
+        }
+        assert_eq!(response["embeds"][0]["title"], "Leaderboard");
+        assert_eq!(response["allowed_mentions"]["users"][0], "1");
+        assert_eq!(response["flags"], 64);
+        assert_eq!(response["attachments"][0]["filename"], "export.csv");
+    }
+
+    #[test]
+    fn split_content_breaks_on_lines_and_reopens_code_blocks() {
+        let content = "intro\n```rust\nlet a = 1;\nlet b = 2;\n```\noutro\n";
+        let chunks = split_content(content, 24);
+        assert_eq!(chunks, ["intro\n", "```rust\nlet a = 1;\n```", "```rust\nlet b = 2;\n```\n", "outro\n"]);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 24), "{chunks:?}");
+
+        let long_line = "x".repeat(25);
+        assert_eq!(split_content(&long_line, 10), ["x".repeat(10), "x".repeat(10), "x".repeat(5)]);
+        assert_eq!(split_content("short", 10), ["short"]);
+
+        let opened_then_long = format!("```json\n{}\n```", "x".repeat(25));
+        let chunks = split_content(&opened_then_long, 20);
+        assert_eq!(chunks, [format!("```json\n{}\n```", "x".repeat(8)), format!("```json\n{}\n```", "x".repeat(8)), format!("```json\n{}\n```", "x".repeat(8)), "```json\nx\n```".to_owned()]);
+
+        let blank_then_long = format!("\n\n{}", "x".repeat(25));
+        let chunks = split_content(&blank_then_long, 10);
+        assert_eq!(chunks, [format!("\n\n{}", "x".repeat(8)), "x".repeat(10), "x".repeat(7)]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{collections::BTreeMap, fmt::Write, ops::RangeInclusive, sync::{LazyLock, Mutex, OnceLock}};
+#[cfg(feature = "database")]
+use std::time::Duration;
+
+use serenity::futures::FutureExt;
+
+use crate::http::{Response, Router};
+#[cfg(feature = "database")]
+use crate::{db::Connector, DatabaseConfiguration};
+
+/// How long `/readyz` waits for a database connection before reporting it unreachable.
+#[cfg(feature = "database")]
+const DB_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a database check is reused, so frequent probes do not each open a connection and
+/// skew the connection metrics.
+#[cfg(feature = "database")]
+const DB_CHECK_TTL: Duration = Duration::from_secs(10);
+
+static HEALTH: LazyLock<HealthState> = LazyLock::new(HealthState::default);
+
+/// Process-wide health state, fed by the gateway event handler.
+pub fn health() -> &'static HealthState {
+    &HEALTH
+}
+
+#[derive(Default)]
+pub struct HealthState {
+    expected_shards: OnceLock<RangeInclusive<u32>>,
+    shards: Mutex<BTreeMap<u32, ShardReadiness>>,
+    #[cfg(feature = "database")]
+    database: OnceLock<DatabaseConfiguration>,
+    /// The last database check, shared by probes arriving within [`DB_CHECK_TTL`] of it.
+    #[cfg(feature = "database")]
+    database_check: tokio::sync::Mutex<Option<DatabaseCheck>>,
+}
+
+#[cfg(feature = "database")]
+#[derive(Debug, Clone, Copy)]
+struct DatabaseCheck {
+    checked_at: tokio::time::Instant,
+    reachable: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShardReadiness {
+    pub gateway_connected: bool,
+    pub commands_registered: bool,
+}
+
+impl ShardReadiness {
+    pub fn is_ready(&self) -> bool {
+        self.gateway_connected && self.commands_registered
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Readiness {
+    /// Every shard this process runs, by shard id.
+    pub shards: BTreeMap<u32, ShardReadiness>,
+    /// `None` when no database is configured.
+    pub database_reachable: Option<bool>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        !self.shards.is_empty() && self.shards.values().all(ShardReadiness::is_ready) && self.database_reachable != Some(false)
+    }
+}
+
+impl HealthState {
+    /// Names the shards this process runs, so `/readyz` waits for all of them. Only the first call counts.
+    pub fn expect_shards(&self, shards: RangeInclusive<u32>) {
+        let _ = self.expected_shards.set(shards);
+    }
+
+    fn update_shard(&self, shard: u32, update: impl FnOnce(&mut ShardReadiness)) {
+        update(self.shards.lock().expect("health lock poisoned").entry(shard).or_default());
+    }
+
+    pub fn set_gateway_connected(&self, shard: u32, connected: bool) {
+        self.update_shard(shard, |s| s.gateway_connected = connected);
+    }
+
+    pub fn set_commands_registered(&self, shard: u32, registered: bool) {
+        self.update_shard(shard, |s| s.commands_registered = registered);
+    }
+
+    /// Makes `/readyz` also check that a connection can be opened to this database.
+    #[cfg(feature = "database")]
+    pub fn set_database(&self, database: DatabaseConfiguration) {
+        let _ = self.database.set(database);
+    }
+
+    #[cfg(feature = "database")]
+    async fn database_reachable(&self) -> Option<bool> {
+        let database = self.database.get()?;
+        let mut last_check = self.database_check.lock().await;
+        if let Some(check) = *last_check
+            && check.checked_at.elapsed() < DB_CHECK_TTL
+        {
+            return Some(check.reachable);
+        }
+        let connected = tokio::time::timeout(DB_CHECK_TIMEOUT, database.async_connect()).await;
+        let reachable = matches!(connected, Ok(Ok(_)));
+        *last_check = Some(DatabaseCheck { checked_at: tokio::time::Instant::now(), reachable });
+        Some(reachable)
+    }
+
+    pub async fn readiness(&self) -> Readiness {
+        let mut shards = self.shards.lock().expect("health lock poisoned").clone();
+        if let Some(expected) = self.expected_shards.get() {
+            for shard in expected.clone() {
+                shards.entry(shard).or_default();
+            }
+        }
+        Readiness {
+            shards,
+            #[cfg(feature = "database")]
+            database_reachable: self.database_reachable().await,
+            #[cfg(not(feature = "database"))]
+            database_reachable: None,
+        }
+    }
+}
+
+fn check(body: &mut String, name: &str, ok: bool) {
+    let _ = writeln!(body, "{name}: {}", if ok { "ok" } else { "fail" });
+}
+
+/// Adds `/healthz`, which answers as long as the process is serving, and `/readyz`, which checks
+/// the gateway connection and command registration of every shard, and the database.
+pub fn route(router: Router) -> Router {
+    router
+        .route("/healthz", || async { Response::text(200, "ok\n") }.boxed())
+        .route("/readyz", || async {
+            let readiness = health().readiness().await;
+            let mut body = String::new();
+            for (id, shard) in &readiness.shards {
+                check(&mut body, &format!("shard {id} gateway"), shard.gateway_connected);
+                check(&mut body, &format!("shard {id} commands"), shard.commands_registered);
+            }
+            if let Some(reachable) = readiness.database_reachable {
+                check(&mut body, "database", reachable);
+            }
+            Response::text(if readiness.is_ready() { 200 } else { 503 }, body)
+        }.boxed())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{Readiness, ShardReadiness};
+
+    #[test]
+    fn ready_needs_every_check() {
+        let ok = ShardReadiness { gateway_connected: true, commands_registered: true };
+        let ready = Readiness { shards: BTreeMap::from([(0, ok), (1, ok)]), database_reachable: None };
+        assert!(ready.is_ready());
+        assert!(!Readiness { database_reachable: Some(false), ..ready.clone() }.is_ready());
+        assert!(!Readiness { shards: BTreeMap::new(), ..ready.clone() }.is_ready());
+        let shards = BTreeMap::from([(0, ok), (1, ShardReadiness { commands_registered: false, ..ok })]);
+        assert!(!Readiness { shards, ..ready.clone() }.is_ready());
+        let shards = BTreeMap::from([(0, ShardReadiness { gateway_connected: false, ..ok }), (1, ok)]);
+        assert!(!Readiness { shards, ..ready }.is_ready());
+    }
+}
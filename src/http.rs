@@ -0,0 +1,159 @@
+use std::{collections::HashMap, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use serenity::futures::future::BoxFuture;
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}};
+use tracing as trc;
+
+/// Requests larger than this are rejected. Only a request line and a few headers are expected.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+/// Connections that have not sent a whole request head within this long are closed.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HttpConfiguration {
+    /// Address to listen on, e.g. `127.0.0.1:9000`.
+    pub bind: SocketAddr,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "",
+        }
+    }
+}
+
+pub type Handler = Box<dyn Fn() -> BoxFuture<'static, Response> + Send + Sync>;
+
+/// Maps `GET` paths to handlers for the embedded server.
+#[derive(Default)]
+pub struct Router {
+    routes: HashMap<&'static str, Handler>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route(mut self, path: &'static str, handler: impl Fn() -> BoxFuture<'static, Response> + Send + Sync + 'static) -> Self {
+        self.routes.insert(path, Box::new(handler));
+        self
+    }
+
+    async fn respond(&self, request: &str) -> Response {
+        let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+        let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+            return Response::text(400, "bad request\n");
+        };
+        if method != "GET" {
+            return Response::text(405, "method not allowed\n");
+        }
+        let path = target.split('?').next().unwrap_or(target);
+        match self.routes.get(path) {
+            Some(handler) => handler().await,
+            None => Response::text(404, "not found\n"),
+        }
+    }
+}
+
+/// Reads up to the end of the request head, or as much as fits in `buf`, returning its length.
+async fn read_request(stream: &mut (impl AsyncRead + Unpin), buf: &mut [u8]) -> io::Result<usize> {
+    let read_head = async {
+        let mut len = 0;
+        while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+            if len == buf.len() {
+                break;
+            }
+            let read = stream.read(&mut buf[len..]).await?;
+            if read == 0 {
+                break;
+            }
+            len += read;
+        }
+        Ok(len)
+    };
+    tokio::time::timeout(READ_TIMEOUT, read_head).await.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request not received in time"))?
+}
+
+async fn handle(mut stream: TcpStream, router: &Router) -> io::Result<()> {
+    let mut buf = vec![0; MAX_REQUEST_LEN];
+    let len = read_request(&mut stream, &mut buf).await?;
+
+    let response = router.respond(String::from_utf8_lossy(&buf[..len]).as_ref()).await;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves `router` until the listener fails. Each connection handles a single request.
+pub async fn serve(cfg: &HttpConfiguration, router: Router) -> io::Result<()> {
+    let listener = TcpListener::bind(cfg.bind).await?;
+    trc::info!(bind = %cfg.bind, "HTTP-LISTEN");
+    let router = Arc::new(router);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let router = Arc::clone(&router);
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &router).await {
+                trc::debug!(peer = %peer, err = %e, "HTTP-CONN-FAIL");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serenity::futures::FutureExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::{read_request, Response, Router};
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_clients_time_out() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let mut buf = [0; 64];
+        client.write_all(b"GET /healthz HTTP/1.1\r\n").await.unwrap();
+        let err = read_request(&mut server, &mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+        client.write_all(b"GET /healthz HTTP/1.1\r\n\r\n").await.unwrap();
+        assert_eq!(read_request(&mut server, &mut buf).await.unwrap(), 25);
+    }
+
+    #[tokio::test]
+    async fn routes_by_path() {
+        let router = Router::new().route("/healthz", || async { Response::text(200, "ok") }.boxed());
+        assert_eq!(router.respond("GET /healthz HTTP/1.1\r\n\r\n").await.status, 200);
+        assert_eq!(router.respond("GET /healthz?verbose HTTP/1.1\r\n\r\n").await.status, 200);
+        assert_eq!(router.respond("GET /missing HTTP/1.1\r\n\r\n").await.status, 404);
+        assert_eq!(router.respond("POST /healthz HTTP/1.1\r\n\r\n").await.status, 405);
+        assert_eq!(router.respond("").await.status, 400);
+    }
+}
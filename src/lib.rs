@@ -1,80 +1,100 @@
 pub mod discord;
+#[cfg(feature = "database")]
 pub mod db;
 
 pub mod cmd;
-
-use cmd::{CommandTreeTop, DiscordCommandDescriptor};
-use config::ConfigError;
+pub mod cfg;
+pub mod cli;
+pub mod log;
+pub mod ops;
+pub mod panics;
+pub mod shutdown;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "http")]
+pub mod health;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "database")]
+pub use cfg::DatabaseConfiguration;
+pub use cfg::{load_configuration, Configuration, ConfigurationError, DiscordConfiguration, HomeGuildConfiguration, ShardingConfiguration};
+pub use cli::{Arguments, CliArguments, DefaultCli};
+
+use std::{collections::HashSet, sync::{Arc, Mutex}};
+
+use cmd::{CommandTreeTop, DefaultErrorReporter, DiscordCommandDescriptor, ErrorReporter};
 use serenity::{all::{ClientBuilder, CommandPermissions, Interaction}, async_trait, client::{Client, EventHandler}, http::CacheHttp, model::{channel::{Message, Reaction}, event::MessageUpdateEvent, gateway::Ready, id::{ChannelId, GuildId, MessageId}}, prelude::{Context as DiscordContext, GatewayIntents}};
 use tracing::{self as trc, Instrument};
+#[cfg(feature = "http")]
+use serenity::gateway::{ConnectionStage, ShardStageUpdateEvent};
 
+#[cfg(feature = "database")]
+use db::settings::GuildSettingsCache;
 use discord::ExecutionContext;
 
-pub struct Arguments {
-    cfg_path: String,
-}
-
-#[derive(serde::Deserialize)]
-pub struct Configuration {
-    discord: DiscordConfiguration,
-    home_guild: HomeGuildConfiguration,
-    database: DatabaseConfiguration,
-}
-
-#[derive(serde::Deserialize)]
-pub struct DatabaseConfiguration {
-    pub url: String,
-}
-
-#[derive(serde::Deserialize)]
-pub struct DiscordConfiguration {
-    token: String,
-    application: u64,
-}
-
-#[derive(serde::Deserialize)]
-pub struct HomeGuildConfiguration {
-    id: u64,
-}
-
 pub struct DiscordHandler<R> {
     pub home_guild_id: GuildId,
-    pub db_cfg: DatabaseConfiguration,
-    pub command_descriptions: Vec<CommandTreeTop<R>>
+    #[cfg(feature = "database")]
+    pub db_cfg: Option<DatabaseConfiguration>,
+    #[cfg(feature = "database")]
+    pub settings_cache: GuildSettingsCache,
+    pub command_descriptions: Vec<CommandTreeTop<R>>,
+    /// Shards that have registered their commands, so a reconnect's `ready` does not redo it.
+    pub registered_shards: Mutex<HashSet<u32>>,
+    pub error_reporter: Arc<dyn ErrorReporter>,
+    /// Where internal errors and panics are posted, from `home_guild.ops_channel`.
+    pub ops_channel: Option<ops::OpsChannel>,
 }
 
 impl <R> DiscordHandler<R> {
     fn show_time<TZ: chrono::TimeZone>(ui: &str, source: &str, data: impl std::fmt::Display, start: chrono::DateTime<TZ>, end: chrono::DateTime<TZ>) {
         let diff = end - start;
-        let diff_ns = diff.num_nanoseconds().unwrap_or(-1);
-        if diff <= chrono::Duration::seconds(1) {
-            let diff_human = diff.num_milliseconds();
-            trc::info!("TIMING ui={ui} {source}={data} duration={diff_ns}ns human={diff_human}ms");
-        } else {
-            let diff_human = diff.num_seconds();
-            trc::info!("TIMING ui={ui} {source}={data} duration={diff_ns}ns human={diff_human}s");
-        }
+        let duration_ns = diff.num_nanoseconds().unwrap_or(-1);
+        let duration_ms = diff.num_milliseconds();
+        trc::Span::current().record("duration_ms", duration_ms);
+        trc::info!(ui, source, id = %data, duration_ns, duration_ms, "TIMING");
     }
 }
 
 #[async_trait]
 impl <R: DiscordCommandDescriptor> EventHandler for DiscordHandler<R> {
     async fn ready(&self, ctx: DiscordContext, data_about_bot: Ready) {
-        trc::info!("CMD-SETUP");
-        let (global_command_descriptions, local_command_descriptions): (Vec<_>, Vec<_>) = self.command_descriptions.clone().into_iter().partition(|cmd| cmd.is_global());
-        let local_commands: Vec<_> = local_command_descriptions.into_iter().map(|ctt| ctt.into_discord_command()).collect();
-        trc::info!("CMD-HOME-GUILD {:?}", self.home_guild_id);
-        for guild in data_about_bot.guilds {
-            trc::info!("CMD-SETUP-GUILD {:?}", guild.id);
-            guild.id.set_commands(
-                ctx.http(),
-                local_commands.clone()
-            ).await.expect("commands should have updated appropriately");
+        let shard = data_about_bot.shard.map_or(0, |info| info.id.0);
+        #[cfg(feature = "http")]
+        {
+            let health = health::health();
+            health.set_gateway_connected(shard, true);
+            if let Some(info) = data_about_bot.shard {
+                health.expect_shards(0..=info.total.saturating_sub(1));
+            }
+        }
+        if self.registered_shards.lock().expect("registered shards lock poisoned").contains(&shard) {
+            trc::info!(shard, "CMD-SETUP-SKIP");
+            return;
+        }
+
+        trc::info!(shard, "CMD-SETUP");
+        trc::info!(home_guild_id = self.home_guild_id.get(), "CMD-HOME-GUILD");
+        let guilds: Vec<_> = data_about_bot.guilds.into_iter().map(|g| g.id).collect();
+        cmd::register_guild_commands(ctx.http(), guilds, &self.command_descriptions).await.expect("guild commands should have updated appropriately");
+        // Global commands are shared by every shard, so only the first one sets them.
+        if shard == 0 {
+            cmd::register_global_commands(ctx.http(), &self.command_descriptions).await.expect("global commands should have updated appropriately");
+        }
+        self.registered_shards.lock().expect("registered shards lock poisoned").insert(shard);
+        #[cfg(feature = "http")]
+        health::health().set_commands_registered(shard, true);
+        trc::info!(shard, "CMD-SETUP-CMPL");
+    }
+
+    #[cfg(feature = "http")]
+    async fn shard_stage_update(&self, _ctx: DiscordContext, event: ShardStageUpdateEvent) {
+        health::health().set_gateway_connected(event.shard_id.0, event.new == ConnectionStage::Connected);
+        #[cfg(feature = "metrics")]
+        if event.old == ConnectionStage::Connected && event.new != ConnectionStage::Connected {
+            metrics::metrics().record_gateway_reconnect(event.shard_id.0);
         }
-        let global_commands: Vec<_> = global_command_descriptions.into_iter().map(|ctt| ctt.into_discord_command()).collect();
-        ctx.http().create_global_commands(&global_commands).await.expect("global commands updated appropriately");
-        trc::info!("CMD-SETUP-GLOBAL");
-        trc::info!("CMD-SETUP-CMPL");
     }
 
     async fn command_permissions_update(
@@ -158,60 +178,146 @@ impl <R: DiscordCommandDescriptor> EventHandler for DiscordHandler<R> {
                     "discord_component"
                 },
                 Interaction::Command(command) => {
+                    let span = trc::Span::current();
+                    let command_path = cmd::command_path(&command);
+                    span.record("command", command_path.as_str());
+                    span.record("user_id", command.user.id.get());
+                    if let Some(guild_id) = command.guild_id {
+                        span.record("guild_id", guild_id.get());
+                    }
+
                     let ctx = ExecutionContext {
                         ctx: &dctx,
                         cmd: &command,
-                        db_cfg: &self.db_cfg,
-                        is_first_response: true.into(),
+                        error_reporter: self.error_reporter.as_ref(),
+                        ops_channel: self.ops_channel.as_ref(),
+                        #[cfg(feature = "database")]
+                        db_cfg: self.db_cfg.as_ref(),
+                        #[cfg(feature = "database")]
+                        settings_cache: &self.settings_cache,
+                        response: Default::default(),
                     };
-                    match cmd::Request::<R>::parse(&command) {
+                    let Some(_in_flight) = shutdown::coordinator().track() else {
+                        trc::info!("REQ-REJECT-SHUTDOWN");
+                        span.record("outcome", "rejected");
+                        if let Err(e) = ctx.reply_ephemeral("The bot is restarting, try again in a moment.".to_owned()).await {
+                            trc::error!(err = ?e, "REQ-REJECT-REPLY-FAIL");
+                        }
+                        return;
+                    };
+                    let (outcome, error_kind) = match cmd::Request::<R>::parse(&command) {
                         Ok(req) => {
-                            trc::info!("REQ-EXEC req={req:?}");
-                            // TODO Execute request.
-                            match req.execute(&ctx).await {
-                                Ok(_) => {
+                            trc::info!(req = ?req, "REQ-EXEC");
+                            let auto_defer = req.auto_defer();
+                            match ctx.with_auto_defer(discord::AUTO_DEFER_AFTER, auto_defer, panics::catch(req.execute(&ctx))).await {
+                                Ok(Ok(_)) => {
                                     trc::info!("REQ-CMP");
+                                    ("success", None)
+                                },
+                                Err(panic) => {
+                                    trc::error!(message = %panic.message, location = %panic.location, backtrace = %panic.backtrace, "REQ-PANIC");
+                                    let err = cmd::RequestError::Internal(cmd::ErrorDetail::new("command panicked").with_source(panic));
+                                    let error_kind = err.kind();
+                                    if let Err(e) = err.report(&ctx).await {
+                                        trc::error!(err = ?e, "REQ-PANIC-REPORT-FAIL");
+                                    }
+                                    ("panic", Some(error_kind))
                                 },
-                                Err(err) => {
-                                    trc::warn!("REQ-FAIL");
+                                Ok(Err(err)) => {
+                                    let error_kind = err.kind();
+                                    trc::warn!(error_kind, "REQ-FAIL");
                                     if let Err(e) = err.report(&ctx).await {
-                                        trc::error!("REQ-EXEC-ERR-REPORT-FAIL err={:?}", e);
+                                        trc::error!(err = ?e, "REQ-EXEC-ERR-REPORT-FAIL");
                                     }
+                                    ("exec_failure", Some(error_kind))
                                 }
                             }
                         },
                         Err(err) => {
-                            trc::warn!("REQ-FAIL cmd={:?}", ctx.cmd);
+                            let error_kind = err.kind();
+                            trc::warn!(error_kind, cmd = ?ctx.cmd, "REQ-FAIL");
                             if let Err(e) = err.report(&ctx).await {
-                                trc::error!("REQ-PARSE-ERR-REPORT-FAIL err={:?}", e);
+                                trc::error!(err = ?e, "REQ-PARSE-ERR-REPORT-FAIL");
                             }
+                            ("parse_failure", Some(error_kind))
                         },
+                    };
+                    span.record("outcome", outcome);
+                    if let Some(error_kind) = error_kind {
+                        span.record("error_kind", error_kind);
                     }
+                    #[cfg(feature = "metrics")]
+                    metrics::metrics().record_command(
+                        command_path.as_str(),
+                        outcome,
+                        error_kind,
+                        (chrono::Utc::now() - start).to_std().unwrap_or_default(),
+                    );
                     "discord_command"
                 },
-                _s => {
-                    panic!("wtf library");
+                other => {
+                    trc::warn!(kind = ?other.kind(), "INTERACTION-UNKNOWN");
+                    "discord_unknown"
                 },
             };
             let end = chrono::Utc::now();
             Self::show_time(ui, "interaction", interaction_id, start, end);
-        }.instrument(trc::info_span!("interaction", primary_id = u64::from(interaction_id))).await;
+        }.instrument(trc::info_span!(
+            "interaction",
+            primary_id = u64::from(interaction_id),
+            command = trc::field::Empty,
+            guild_id = trc::field::Empty,
+            user_id = trc::field::Empty,
+            outcome = trc::field::Empty,
+            error_kind = trc::field::Empty,
+            duration_ms = trc::field::Empty,
+        )).await;
     }
 }
 
 pub struct Discord(pub Client);
 
+impl Discord {
+    /// Connects the shards `sharding` assigns to this process and runs until they shut down.
+    pub async fn start(&mut self, sharding: &ShardingConfiguration) -> serenity::Result<()> {
+        match *sharding {
+            ShardingConfiguration::Auto => self.0.start_autosharded().await,
+            ShardingConfiguration::Fixed { count } => self.0.start_shards(count).await,
+            // serenity treats the end of the range as inclusive.
+            ShardingConfiguration::Range { first, last, total } => self.0.start_shard_range(first..last, total).await,
+        }
+    }
+}
+
 pub async fn build_client<R: DiscordCommandDescriptor>(
     cfg: Configuration,
     command_descriptions: Vec<CommandTreeTop<R>>,
     builder_config_fn: impl FnOnce(ClientBuilder) -> ClientBuilder,
+) -> serenity::Result<Discord> {
+    build_client_with_reporter(cfg, command_descriptions, Arc::new(DefaultErrorReporter), builder_config_fn).await
+}
+
+/// Like [`build_client`], with failed commands presented by `error_reporter`.
+pub async fn build_client_with_reporter<R: DiscordCommandDescriptor>(
+    cfg: Configuration,
+    command_descriptions: Vec<CommandTreeTop<R>>,
+    error_reporter: Arc<dyn ErrorReporter>,
+    builder_config_fn: impl FnOnce(ClientBuilder) -> ClientBuilder,
 ) -> serenity::Result<Discord> {
     let token = cfg.discord.token.as_str();
     let application_id = cfg.discord.application.into();
     let handler = DiscordHandler {
         home_guild_id: cfg.home_guild.id.into(),
+        #[cfg(feature = "database")]
         db_cfg: cfg.database,
+        #[cfg(feature = "database")]
+        settings_cache: GuildSettingsCache::new(),
         command_descriptions,
+        registered_shards: Default::default(),
+        error_reporter,
+        ops_channel: cfg.home_guild.ops_channel.map(|channel| {
+            ops::OpsChannel::new(channel.into(), std::time::Duration::from_secs(cfg.home_guild.ops_repeat_window_secs))
+        }),
     };
 
     let intents = GatewayIntents::non_privileged();
@@ -226,40 +332,131 @@ pub async fn build_client<R: DiscordCommandDescriptor>(
     Ok(Discord(client))
 }
 
-pub fn load_configuration(cfg_path: &str) -> Result<Configuration, ConfigError> {
-    config::Config::builder()
-        .add_source(config::File::with_name(cfg_path))
-        .build()?
-        .try_deserialize()
-}
+/// Parses the command line as `A`, loads the configuration it names and sets up logging from both.
+///
+/// A bad invocation prints usage and exits.
+pub fn setup_log_and_load_configuration<A: CliArguments>() -> Result<(A, Configuration), ConfigurationError> {
+    let args = A::parse();
+    let azel_args = args.azel();
 
-pub fn setup_default_log_and_load_configuration() -> Result<Configuration, ConfigError> {
-    tracing_subscriber::fmt::init();
+    // Logging is configured from the file, so what loading it read is only logged once it is set up.
+    let (loaded, sources) = cfg::load_configuration_with_sources(azel_args.cfg_path());
+    let default_logging = log::LoggingConfiguration::default();
+    let logging = loaded.as_ref().map_or(&default_logging, |cfg| &cfg.logging);
+    log::init(logging, azel_args.log_level.as_deref(), azel_args.log_format);
 
     trc::info!("LOG-CMPL");
+    sources.log();
 
-    let Arguments { cfg_path } = {
-        let mut args: Vec<String> = std::env::args().collect();
-        if args.len() != 2 {
-            trc::error!("BAD-ARGS args={:?}", args);
-            panic!("bad args");
-        }
-        let cfg_path = args.remove(1);
-        Arguments {
-            cfg_path,
+    let cfg = loaded?;
+    trc::info!("CFG-LOADED cfg={cfg:?}");
+    Ok((args, cfg))
+}
+
+pub fn setup_default_log_and_load_configuration() -> Result<(Arguments, Configuration), ConfigurationError> {
+    setup_log_and_load_configuration::<DefaultCli>().map(|(cli, cfg)| (cli.azel, cfg))
+}
+
+/// Registers commands over HTTP only, for every guild the bot is in, without opening a gateway connection.
+pub async fn register_commands_only<R: DiscordCommandDescriptor>(cfg: &Configuration, command_descriptions: &[CommandTreeTop<R>]) -> serenity::Result<()> {
+    let http = serenity::http::Http::new(cfg.discord.token.as_str());
+    http.set_application_id(cfg.discord.application.into());
+
+    let mut guilds = vec![];
+    loop {
+        let page = http.get_guilds(guilds.last().map(|g: &GuildId| serenity::http::GuildPagination::After(*g)), Some(200)).await?;
+        let page_len = page.len();
+        guilds.extend(page.into_iter().map(|g| g.id));
+        if page_len < 200 {
+            break;
         }
-    };
+    }
 
+    trc::info!("CMD-SETUP");
+    cmd::register_commands(&http, guilds, command_descriptions).await?;
+    trc::info!("CMD-SETUP-CMPL");
+    Ok(())
+}
 
-    load_configuration(cfg_path.as_str())
+/// Runs the bot as directed by `args`: a command export, a dry run, a registration-only run, or a normal run.
+pub async fn run_with_arguments<R: DiscordCommandDescriptor>(args: &Arguments, cfg: Configuration, command_descriptions: Vec<CommandTreeTop<R>>) {
+    run_with_reporter(args, cfg, command_descriptions, Arc::new(DefaultErrorReporter)).await
 }
 
-pub async fn easy_setup_and_run<R: DiscordCommandDescriptor>(command_descriptions: Vec<CommandTreeTop<R>>) {
-    let cfg = setup_default_log_and_load_configuration().unwrap();
+/// Like [`run_with_arguments`], with failed commands presented by `error_reporter`.
+pub async fn run_with_reporter<R: DiscordCommandDescriptor>(
+    args: &Arguments,
+    cfg: Configuration,
+    command_descriptions: Vec<CommandTreeTop<R>>,
+    error_reporter: Arc<dyn ErrorReporter>,
+) {
+    if let Some(path) = args.export_commands.as_deref() {
+        cmd::CommandExport::new(&command_descriptions).write(path).expect("command export to be written");
+        trc::info!(path, "CMD-EXPORT");
+        return;
+    }
 
-    let mut discord = build_client(cfg, command_descriptions, |b| b).await.expect("client to be built");
+    if args.dry_run {
+        let (global_commands, local_commands) = cmd::partition_commands(&command_descriptions);
+        trc::info!(global_commands = global_commands.len(), guild_commands = local_commands.len(), "DRY-RUN");
+        return;
+    }
+
+    if args.register_only {
+        register_commands_only(&cfg, &command_descriptions).await.expect("commands to be registered");
+        return;
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(http_cfg) = cfg.http.clone() {
+        if let Some(shards) = cfg.sharding.local_shards() {
+            health::health().expect_shards(shards);
+        }
+        #[cfg(feature = "database")]
+        if let Some(database) = cfg.database.clone() {
+            health::health().set_database(database);
+        }
+        let router = health::route(http::Router::new());
+        #[cfg(feature = "metrics")]
+        let router = metrics::route(router);
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(&http_cfg, router).await {
+                trc::error!(err = %e, "HTTP-FAIL");
+            }
+        });
+    }
 
-    trc::info!("BOOT-CMPL");
+    let grace_period = cfg.shutdown.grace_period();
+    let sharding = cfg.sharding.clone();
+    let mut discord = build_client_with_reporter(cfg, command_descriptions, error_reporter, |b| b).await.expect("client to be built");
+
+    let shard_manager = discord.0.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        let coordinator = shutdown::coordinator();
+        trc::info!(in_flight = coordinator.in_flight(), grace_period_secs = grace_period.as_secs(), "SHUTDOWN-BEGIN");
+        if !coordinator.drain(grace_period).await {
+            trc::warn!(in_flight = coordinator.in_flight(), "SHUTDOWN-GRACE-EXPIRED");
+        }
+        shard_manager.shutdown_all().await;
+    });
+
+    trc::info!(sharding = ?sharding, "BOOT-CMPL");
+
+    discord.start(&sharding).await.expect("no error");
+
+    shutdown::coordinator().run_hooks().await;
+    trc::info!("SHUTDOWN-CMPL");
+}
+
+pub async fn easy_setup_and_run<R: DiscordCommandDescriptor>(command_descriptions: Vec<CommandTreeTop<R>>) {
+    let (args, cfg) = match setup_default_log_and_load_configuration() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            trc::error!("CFG-FAIL {e}");
+            std::process::exit(1);
+        },
+    };
 
-    discord.0.start().await.expect("no error");
+    run_with_arguments(&args, cfg, command_descriptions).await;
 }
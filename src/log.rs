@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use tracing as trc;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::{filter::ParseError, fmt::{format::FmtSpan, MakeWriter}, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Filter used when neither `--log-level`, `logging.level` nor `RUST_LOG` is set.
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Single-line human readable output.
+    #[default]
+    Full,
+    Compact,
+    /// Multi-line human readable output.
+    Pretty,
+    /// One JSON object per line, for log aggregation.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+fn default_file_prefix() -> String {
+    "azel".to_owned()
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct LogFileConfiguration {
+    pub directory: PathBuf,
+    #[serde(default = "default_file_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// Oldest files past this count are deleted on rotation. Keeps everything when unset.
+    pub max_files: Option<usize>,
+}
+
+/// The `logging` section of the configuration. Every field is optional.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct LoggingConfiguration {
+    /// Base filter in `RUST_LOG` syntax, e.g. `info,azel=debug`. Falls back to `RUST_LOG`, then `info`.
+    pub level: Option<String>,
+    /// Per-module levels layered on top of `level`, e.g. `serenity = "warn"`.
+    pub modules: BTreeMap<String, String>,
+    /// Format of stdout output. Files always use the same format, without colour.
+    pub format: LogFormat,
+    /// Emits an event with busy and idle time whenever a span closes.
+    pub span_timing: bool,
+    /// Also writes logs to rotating files.
+    pub file: Option<LogFileConfiguration>,
+}
+
+impl LoggingConfiguration {
+    pub fn filter(&self) -> Result<EnvFilter, ParseError> {
+        let mut filter = match self.level.as_deref() {
+            Some(level) => EnvFilter::try_new(level)?,
+            None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(DEFAULT_FILTER))?,
+        };
+        for (module, level) in &self.modules {
+            filter = filter.add_directive(format!("{module}={level}").parse()?);
+        }
+        Ok(filter)
+    }
+
+    fn file_appender(&self) -> Option<Result<RollingFileAppender, InitError>> {
+        let file = self.file.as_ref()?;
+        let mut builder = RollingFileAppender::builder()
+            .rotation(file.rotation.into())
+            .filename_prefix(file.prefix.as_str())
+            .filename_suffix("log");
+        if let Some(max_files) = file.max_files {
+            builder = builder.max_log_files(max_files);
+        }
+        Some(builder.build(&file.directory))
+    }
+}
+
+fn fmt_layer<S, W>(format: LogFormat, span_timing: bool, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: trc::Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let span_events = if span_timing { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_span_events(span_events);
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Installs the global subscriber, bridging `log` records into it.
+///
+/// `filter_override` and `format_override` come from the command line and replace `logging.level`,
+/// `logging.modules` and `logging.format`. A log file that cannot be opened is reported and skipped.
+pub fn init(cfg: &LoggingConfiguration, filter_override: Option<&str>, format_override: Option<LogFormat>) {
+    let (filter, filter_err) = match filter_override.map_or_else(|| cfg.filter(), EnvFilter::try_new) {
+        Ok(filter) => (filter, None),
+        Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some(e)),
+    };
+    let format = format_override.unwrap_or(cfg.format);
+
+    let (file_layer, file_err) = match cfg.file_appender() {
+        Some(Ok(appender)) => (Some(fmt_layer(format, cfg.span_timing, appender, false)), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer(format, cfg.span_timing, std::io::stdout, true))
+        .with(file_layer)
+        .try_init();
+    if let Err(e) = installed {
+        eprintln!("LOG-INIT-FAIL err={e}");
+        return;
+    }
+
+    if let Some(e) = filter_err {
+        trc::warn!(err = %e, fallback = DEFAULT_FILTER, "LOG-FILTER-INVALID");
+    }
+    if let Some(e) = file_err {
+        trc::warn!(err = %e, "LOG-FILE-FAIL");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::LoggingConfiguration;
+
+    #[test]
+    fn filter_layers_module_levels() {
+        let cfg = LoggingConfiguration {
+            level: Some("info".to_owned()),
+            modules: BTreeMap::from([("serenity".to_owned(), "warn".to_owned())]),
+            ..Default::default()
+        };
+        let filter = cfg.filter().unwrap().to_string();
+        assert!(filter.contains("serenity=warn"), "{filter}");
+        assert!(filter.contains("info"), "{filter}");
+    }
+
+    #[test]
+    fn filter_rejects_bad_levels() {
+        let cfg = LoggingConfiguration {
+            modules: BTreeMap::from([("serenity".to_owned(), "loud".to_owned())]),
+            ..Default::default()
+        };
+        assert!(cfg.filter().is_err());
+    }
+}
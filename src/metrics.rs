@@ -0,0 +1,131 @@
+use std::{sync::LazyLock, time::Duration};
+
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
+use serenity::futures::FutureExt;
+
+use crate::http::{Response, Router};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process-wide metrics. Bots can register their own collectors on [`Metrics::registry`].
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    registry: Registry,
+    command_invocations: IntCounterVec,
+    command_duration: HistogramVec,
+    request_errors: IntCounterVec,
+    gateway_reconnects: IntCounterVec,
+    db_connections: IntCounterVec,
+    db_connect_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("azel".to_owned()), None).expect("metric prefix to be valid");
+
+        let command_invocations = IntCounterVec::new(
+            Opts::new("command_invocations_total", "Commands invoked, by command and outcome."),
+            &["command", "outcome"],
+        ).expect("metric to be valid");
+        let command_duration = HistogramVec::new(
+            HistogramOpts::new("command_duration_seconds", "Time from receiving a command to finishing it, including error reporting.")
+                .buckets(vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 10.0, 30.0]),
+            &["command"],
+        ).expect("metric to be valid");
+        let request_errors = IntCounterVec::new(
+            Opts::new("request_errors_total", "Failed commands, by command and `RequestError` kind."),
+            &["command", "kind"],
+        ).expect("metric to be valid");
+        let gateway_reconnects = IntCounterVec::new(
+            Opts::new("gateway_reconnects_total", "Times a connected shard dropped and began reconnecting."),
+            &["shard"],
+        ).expect("metric to be valid");
+        let db_connections = IntCounterVec::new(
+            Opts::new("db_connections_total", "Database connections opened through `Connector`, by backend and outcome."),
+            &["backend", "outcome"],
+        ).expect("metric to be valid");
+        let db_connect_duration = HistogramVec::new(
+            HistogramOpts::new("db_connect_duration_seconds", "Time taken to open a database connection."),
+            &["backend"],
+        ).expect("metric to be valid");
+
+        for collector in [
+            Box::new(command_invocations.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(command_duration.clone()),
+            Box::new(request_errors.clone()),
+            Box::new(gateway_reconnects.clone()),
+            Box::new(db_connections.clone()),
+            Box::new(db_connect_duration.clone()),
+        ] {
+            registry.register(collector).expect("metric to register once");
+        }
+
+        Self {
+            registry,
+            command_invocations,
+            command_duration,
+            request_errors,
+            gateway_reconnects,
+            db_connections,
+            db_connect_duration,
+        }
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    pub fn record_command(&self, command: &str, outcome: &str, error_kind: Option<&str>, duration: Duration) {
+        self.command_invocations.with_label_values(&[command, outcome]).inc();
+        self.command_duration.with_label_values(&[command]).observe(duration.as_secs_f64());
+        if let Some(kind) = error_kind {
+            self.request_errors.with_label_values(&[command, kind]).inc();
+        }
+    }
+
+    pub fn record_gateway_reconnect(&self, shard: u32) {
+        self.gateway_reconnects.with_label_values(&[shard.to_string().as_str()]).inc();
+    }
+
+    pub fn record_db_connect(&self, backend: &str, succeeded: bool, duration: Duration) {
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.db_connections.with_label_values(&[backend, outcome]).inc();
+        self.db_connect_duration.with_label_values(&[backend]).observe(duration.as_secs_f64());
+    }
+
+    /// Encodes every registered metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buf = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf).expect("metrics to encode");
+        String::from_utf8(buf).expect("prometheus text format to be utf-8")
+    }
+}
+
+/// Adds `/metrics` to the embedded server.
+pub fn route(router: Router) -> Router {
+    router.route("/metrics", || async {
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: metrics().render(),
+        }
+    }.boxed())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::metrics;
+
+    #[test]
+    fn render_includes_recorded_commands() {
+        metrics().record_command("settings show", "exec_failure", Some("user"), Duration::from_millis(20));
+        let rendered = metrics().render();
+        assert!(rendered.contains(r#"azel_command_invocations_total{command="settings show",outcome="exec_failure"} 1"#), "{rendered}");
+        assert!(rendered.contains(r#"azel_request_errors_total{command="settings show",kind="user"} 1"#), "{rendered}");
+    }
+}
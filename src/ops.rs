@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fmt::Write, sync::Mutex, time::{Duration, Instant}};
+
+use serenity::{all::{ChannelId, CreateAllowedMentions, CreateMessage, GuildId, UserId}, http::Http};
+use tracing as trc;
+
+/// Longest reason or cause chain posted, so a report stays well inside one message.
+const MAX_DETAIL_LEN: usize = 600;
+
+/// A failure worth a human's attention, as posted to the ops channel.
+#[derive(Debug)]
+pub struct OpsReport<'a> {
+    /// `internal` or `panic`.
+    pub kind: &'static str,
+    pub command: &'a str,
+    pub user_id: UserId,
+    pub guild_id: Option<GuildId>,
+    pub reason: &'a str,
+    pub causes: &'a str,
+    pub correlation_id: &'a str,
+}
+
+fn truncated(text: &str) -> String {
+    match text.char_indices().nth(MAX_DETAIL_LEN) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text.to_owned(),
+    }
+}
+
+impl OpsReport<'_> {
+    /// Reports with the same fingerprint count as repeats of each other.
+    fn fingerprint(&self) -> String {
+        format!("{}\0{}\0{}", self.kind, self.command, self.reason)
+    }
+
+    fn render(&self, suppressed: u32) -> String {
+        let mut text = format!("**{}** `{}` in `/{}`\n", self.kind, self.correlation_id, self.command);
+        let _ = writeln!(text, "User: <@{0}> ({0})", self.user_id);
+        let _ = writeln!(text, "Guild: {}", self.guild_id.map_or_else(|| "none".to_owned(), |g| g.to_string()));
+        let _ = writeln!(text, "Reason: {}", truncated(self.reason));
+        if !self.causes.is_empty() {
+            let _ = writeln!(text, "Causes: {}", truncated(self.causes));
+        }
+        if suppressed > 0 {
+            let _ = writeln!(text, "Also happened {suppressed} more times since it was last posted.");
+        }
+        text
+    }
+}
+
+struct Posted {
+    at: Instant,
+    suppressed: u32,
+}
+
+/// Posts internal errors and panics to a channel in the home guild, holding back repeats.
+pub struct OpsChannel {
+    channel: ChannelId,
+    repeat_window: Duration,
+    posted: Mutex<HashMap<String, Posted>>,
+}
+
+impl OpsChannel {
+    pub fn new(channel: ChannelId, repeat_window: Duration) -> Self {
+        Self { channel, repeat_window, posted: Mutex::default() }
+    }
+
+    /// Returns how many repeats were held back since the last post, or `None` to hold this one back too.
+    fn admit(&self, fingerprint: String, now: Instant) -> Option<u32> {
+        let mut posted = self.posted.lock().expect("ops channel lock poisoned");
+        let recent = |p: &Posted| now.duration_since(p.at) < self.repeat_window;
+        let suppressed = match posted.get_mut(&fingerprint) {
+            Some(last) if recent(last) => {
+                last.suppressed += 1;
+                return None;
+            },
+            Some(last) => last.suppressed,
+            None => 0,
+        };
+        // Expired entries still holding back repeats stay, so the count is reported when they recur.
+        posted.retain(|_, p| recent(p) || p.suppressed > 0);
+        posted.insert(fingerprint, Posted { at: now, suppressed: 0 });
+        Some(suppressed)
+    }
+
+    pub async fn forward(&self, http: &Http, report: OpsReport<'_>) {
+        let Some(suppressed) = self.admit(report.fingerprint(), Instant::now()) else {
+            trc::debug!(correlation_id = report.correlation_id, "OPS-SUPPRESSED");
+            return;
+        };
+        let message = CreateMessage::new()
+            .content(report.render(suppressed))
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(e) = self.channel.send_message(http, message).await {
+            trc::warn!(correlation_id = report.correlation_id, err = ?e, "OPS-FORWARD-FAILED");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use serenity::all::ChannelId;
+
+    use super::OpsChannel;
+
+    #[test]
+    fn repeats_are_held_back_within_the_window() {
+        let ops = OpsChannel::new(ChannelId::new(1), Duration::from_secs(60));
+        let start = Instant::now();
+        assert_eq!(ops.admit("a".to_owned(), start), Some(0));
+        assert_eq!(ops.admit("a".to_owned(), start + Duration::from_secs(1)), None);
+        assert_eq!(ops.admit("a".to_owned(), start + Duration::from_secs(2)), None);
+        assert_eq!(ops.admit("b".to_owned(), start + Duration::from_secs(3)), Some(0));
+        assert_eq!(ops.admit("b".to_owned(), start + Duration::from_secs(64)), Some(0));
+        assert_eq!(ops.admit("a".to_owned(), start + Duration::from_secs(65)), Some(2));
+    }
+}
@@ -0,0 +1,80 @@
+use std::{any::Any, backtrace::Backtrace, cell::{Cell, RefCell}, fmt, future::{poll_fn, Future}, panic::{AssertUnwindSafe, Location}, sync::Once};
+
+use serenity::futures::FutureExt;
+
+thread_local! {
+    /// Set while this thread polls a future under [`catch`], so the hook knows the panic is handled.
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    /// Where the last caught panic on this thread happened, and the stack at that point.
+    static LAST_PANIC: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+/// Records the location and a backtrace of panics inside [`catch`], and leaves every other panic to
+/// the hook that was installed before.
+fn install_hook() {
+    HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if CATCHING.get() {
+                let location = info.location().map_or_else(|| "unknown".to_owned(), Location::to_string);
+                LAST_PANIC.set(Some((location, Backtrace::force_capture())));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// A panic caught by [`catch`].
+#[derive(Debug)]
+pub struct Panic {
+    pub message: String,
+    pub location: String,
+    pub backtrace: Backtrace,
+}
+
+impl Panic {
+    fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let message = payload.downcast_ref::<&str>().map(|s| (*s).to_owned())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".to_owned());
+        let (location, backtrace) = LAST_PANIC.take().unwrap_or_else(|| ("unknown".to_owned(), Backtrace::disabled()));
+        Self { message, location, backtrace }
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked at {}: {}", self.location, self.message)
+    }
+}
+
+impl std::error::Error for Panic {}
+
+/// Drives `work`, turning a panic inside it into an error instead of unwinding further.
+pub async fn catch<F: Future>(work: F) -> Result<F::Output, Panic> {
+    install_hook();
+    let mut work = std::pin::pin!(AssertUnwindSafe(work).catch_unwind());
+    poll_fn(|cx| {
+        let outer = CATCHING.replace(true);
+        let polled = work.as_mut().poll(cx);
+        CATCHING.set(outer);
+        polled
+    }).await.map_err(Panic::from_payload)
+}
+
+#[cfg(test)]
+mod test {
+    use super::catch;
+
+    #[tokio::test]
+    async fn panics_become_errors() {
+        assert_eq!(catch(async { 1 }).await.unwrap(), 1);
+
+        let panic = catch(async { panic!("boom {}", 2) }).await.unwrap_err();
+        assert_eq!(panic.message, "boom 2");
+        assert!(panic.location.contains("panics.rs"), "{}", panic.location);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, LazyLock, Mutex}, time::Duration};
+
+use serenity::futures::{future::BoxFuture, FutureExt};
+use tokio::sync::Notify;
+use tracing as trc;
+
+static COORDINATOR: LazyLock<Shutdown> = LazyLock::new(Shutdown::default);
+
+/// Process-wide shutdown coordinator used by the event handler and [`crate::run_with_arguments`].
+pub fn coordinator() -> &'static Shutdown {
+    &COORDINATOR
+}
+
+/// Registers `hook` to run once the gateway has closed during a graceful shutdown.
+///
+/// Hooks run one at a time in registration order, e.g. to flush buffers or close pools the bot owns.
+pub fn on_shutdown<F, Fut>(hook: F)
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    coordinator().on_shutdown(hook);
+}
+
+fn default_grace_period_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ShutdownConfiguration {
+    /// How long in-flight commands get to finish after a shutdown signal.
+    #[serde(default = "default_grace_period_secs")]
+    pub grace_period_secs: u64,
+}
+
+impl Default for ShutdownConfiguration {
+    fn default() -> Self {
+        Self {
+            grace_period_secs: default_grace_period_secs(),
+        }
+    }
+}
+
+impl ShutdownConfiguration {
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.grace_period_secs)
+    }
+}
+
+type ShutdownHook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+#[derive(Default)]
+pub struct Shutdown {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    hooks: Mutex<Vec<ShutdownHook>>,
+}
+
+/// Marks one command as in flight until dropped.
+pub struct InFlight<'a>(&'a Shutdown);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Starts tracking a command, or returns `None` once shutdown has begun.
+    pub fn track(&self) -> Option<InFlight<'_>> {
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let guard = InFlight(self);
+        if self.is_draining() {
+            return None;
+        }
+        Some(guard)
+    }
+
+    pub fn on_shutdown<F, Fut>(&self, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.lock().expect("shutdown hooks lock poisoned").push(Box::new(move || hook().boxed()));
+    }
+
+    /// Stops accepting new commands and waits up to `grace_period` for in-flight ones.
+    ///
+    /// Returns `false` if commands were still running when the grace period ran out.
+    pub async fn drain(&self, grace_period: Duration) -> bool {
+        self.draining.store(true, Ordering::Release);
+        tokio::time::timeout(grace_period, async {
+            loop {
+                let idle = self.idle.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        }).await.is_ok()
+    }
+
+    pub async fn run_hooks(&self) {
+        let hooks = std::mem::take(&mut *self.hooks.lock().expect("shutdown hooks lock poisoned"));
+        for hook in hooks {
+            hook().await;
+        }
+    }
+}
+
+/// Resolves on SIGINT, or on SIGTERM where it exists.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = terminate.recv() => {},
+                }
+                return;
+            },
+            Err(e) => trc::warn!(err = %e, "SHUTDOWN-SIGTERM-UNAVAILABLE"),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        trc::error!(err = %e, "SHUTDOWN-SIGNAL-FAIL");
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Shutdown;
+
+    #[tokio::test]
+    async fn drain_waits_for_in_flight_and_refuses_new() {
+        let shutdown = Shutdown::default();
+        let guard = shutdown.track().unwrap();
+        assert_eq!(shutdown.in_flight(), 1);
+
+        let (drained, _) = tokio::join!(shutdown.drain(Duration::from_secs(5)), async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+        assert!(drained);
+        assert!(shutdown.track().is_none());
+        assert_eq!(shutdown.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn drain_gives_up_after_grace_period() {
+        let shutdown = Shutdown::default();
+        let _guard = shutdown.track().unwrap();
+        assert!(!shutdown.drain(Duration::from_millis(10)).await);
+    }
+}